@@ -0,0 +1,142 @@
+use crate::dynvec::{Generation, Handle, next_arena_id};
+use alloc::vec::Vec;
+use core::num::NonZeroU32;
+
+/// Number of slots per chunk.
+pub const CHUNK_SIZE: usize = 256;
+
+#[derive(Debug)]
+struct Slot<T, G> {
+    generation: G,
+    val: Option<T>,
+}
+
+/// A `DynVec` variant whose storage grows in fixed-size chunks, so elements never move:
+/// a `&T` obtained from `get` points at the same address for as long as the element lives,
+/// no matter how many inserts follow. Handles are flat indices, decoded as
+/// `(idx / CHUNK_SIZE, idx % CHUNK_SIZE)`.
+#[derive(Debug)]
+pub struct ChunkedDynVec<T, G = NonZeroU32> {
+    // Each chunk is allocated with `CHUNK_SIZE` capacity up front and never pushed past it,
+    // so it never reallocates.
+    chunks: Vec<Vec<Slot<T, G>>>,
+    free: Vec<usize>,
+    len: usize,
+    id: u64,
+}
+
+impl<T, G> Default for ChunkedDynVec<T, G> {
+    fn default() -> Self {
+        Self {
+            chunks: Vec::new(),
+            free: Vec::new(),
+            len: 0,
+            id: next_arena_id(),
+        }
+    }
+}
+
+impl<T> ChunkedDynVec<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<T, G: Generation> ChunkedDynVec<T, G> {
+    /// Inserts a new item, reusing a freed slot if there is one.
+    pub fn insert(&mut self, value: T) -> Handle<T, G> {
+        self.len += 1;
+        if let Some(idx) = self.free.pop() {
+            let slot = &mut self.chunks[idx / CHUNK_SIZE][idx % CHUNK_SIZE];
+            slot.val = Some(value);
+            return Handle::issued(self.id, idx, slot.generation);
+        }
+        if self
+            .chunks
+            .last()
+            .is_none_or(|chunk| chunk.len() == CHUNK_SIZE)
+        {
+            self.chunks.push(Vec::with_capacity(CHUNK_SIZE));
+        }
+        let chunk_idx = self.chunks.len() - 1;
+        let chunk = &mut self.chunks[chunk_idx];
+        let idx = chunk_idx * CHUNK_SIZE + chunk.len();
+        chunk.push(Slot {
+            generation: G::first(),
+            val: Some(value),
+        });
+        Handle::issued(self.id, idx, G::first())
+    }
+
+    /// Get a reference to the value; its address is stable until the element is removed.
+    pub fn get(&self, h: Handle<T, G>) -> Option<&T> {
+        self.slot(h)?.val.as_ref()
+    }
+
+    /// Get a mutable reference to the value (no gen bump).
+    pub fn get_mut(&mut self, h: Handle<T, G>) -> Option<&mut T> {
+        self.slot_mut(h)?.val.as_mut()
+    }
+
+    /// Checks whether the handle still points at a live element.
+    pub fn contains(&self, h: Handle<T, G>) -> bool {
+        self.get(h).is_some()
+    }
+
+    /// Deletes the item: bumps generation and frees the slot, or retires it for good
+    /// when the generation is exhausted.
+    pub fn remove(&mut self, h: Handle<T, G>) -> Option<T> {
+        let slot = self.slot_mut(h)?;
+        let old = slot.val.take()?;
+        if let Some(generation) = slot.generation.bump() {
+            slot.generation = generation;
+            self.free.push(h.idx);
+        }
+        self.len -= 1;
+        Some(old)
+    }
+
+    /// Iterate over live elements, yielding a valid handle alongside each value.
+    pub fn iter(&self) -> impl Iterator<Item = (Handle<T, G>, &T)> {
+        let arena = self.id;
+        self.chunks
+            .iter()
+            .flatten()
+            .enumerate()
+            .filter_map(move |(idx, slot)| {
+                let val = slot.val.as_ref()?;
+                Some((Handle::issued(arena, idx, slot.generation), val))
+            })
+    }
+
+    /// Get the number of live elements.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn slot(&self, h: Handle<T, G>) -> Option<&Slot<T, G>> {
+        if !h.issued_by(self.id) {
+            return None;
+        }
+        let slot = self
+            .chunks
+            .get(h.idx / CHUNK_SIZE)?
+            .get(h.idx % CHUNK_SIZE)?;
+        (slot.generation == h.generation).then_some(slot)
+    }
+
+    fn slot_mut(&mut self, h: Handle<T, G>) -> Option<&mut Slot<T, G>> {
+        if !h.issued_by(self.id) {
+            return None;
+        }
+        let slot = self
+            .chunks
+            .get_mut(h.idx / CHUNK_SIZE)?
+            .get_mut(h.idx % CHUNK_SIZE)?;
+        (slot.generation == h.generation).then_some(slot)
+    }
+}
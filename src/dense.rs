@@ -0,0 +1,169 @@
+use crate::dynvec::{Generation, Handle, next_arena_id};
+use alloc::vec::Vec;
+use core::num::NonZeroU32;
+
+/// One entry of the handle table: the generation handles to it must carry, and where its
+/// value sits in `values` while it is live.
+#[derive(Debug)]
+struct Entry<G> {
+    generation: G,
+    position: Option<usize>,
+}
+
+/// An arena whose live values are always packed into one `Vec<T>`, so it can lend them as a
+/// `&[T]` (e.g. to FFI or BLAS) without copying. Handles index a table that points into the
+/// values, so every lookup pays one extra indexing step. `remove` moves the last value into
+/// the hole, so the slice is in no particular order; `iter` pairs each value with its handle.
+#[derive(Debug)]
+pub struct DenseDynVec<T, G = NonZeroU32> {
+    values: Vec<T>,
+    // The table entry of each value, to repoint the entry of the value `remove` moves
+    owners: Vec<usize>,
+    table: Vec<Entry<G>>,
+    free: Vec<usize>,
+    id: u64,
+}
+
+impl<T, G> Default for DenseDynVec<T, G> {
+    fn default() -> Self {
+        Self {
+            values: Vec::new(),
+            owners: Vec::new(),
+            table: Vec::new(),
+            free: Vec::new(),
+            id: next_arena_id(),
+        }
+    }
+}
+
+impl<T> DenseDynVec<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<T, G: Generation> DenseDynVec<T, G> {
+    /// Appends the value, reusing a freed table entry for its handle if there is one.
+    pub fn insert(&mut self, value: T) -> Handle<T, G> {
+        let idx = self.free.pop().unwrap_or_else(|| {
+            self.table.push(Entry {
+                generation: G::first(),
+                position: None,
+            });
+            self.table.len() - 1
+        });
+        let entry = &mut self.table[idx];
+        entry.position = Some(self.values.len());
+        self.values.push(value);
+        self.owners.push(idx);
+        Handle::issued(self.id, idx, entry.generation)
+    }
+
+    /// Get a reference to the value, through the handle table.
+    pub fn get(&self, h: Handle<T, G>) -> Option<&T> {
+        self.values.get(self.position(h)?)
+    }
+
+    /// Get a mutable reference to the value (no gen bump).
+    pub fn get_mut(&mut self, h: Handle<T, G>) -> Option<&mut T> {
+        let position = self.position(h)?;
+        self.values.get_mut(position)
+    }
+
+    /// Checks whether the handle still points at a live value.
+    pub fn contains(&self, h: Handle<T, G>) -> bool {
+        self.position(h).is_some()
+    }
+
+    /// Deletes the value and fills its place with the last one. The table entry's generation
+    /// is bumped and the entry freed, or retired for good when the generation is exhausted.
+    pub fn remove(&mut self, h: Handle<T, G>) -> Option<T> {
+        let position = self.position(h)?;
+        let entry = &mut self.table[h.idx];
+        entry.position = None;
+        if let Some(generation) = entry.generation.bump() {
+            entry.generation = generation;
+            self.free.push(h.idx);
+        }
+        self.owners.swap_remove(position);
+        if let Some(&moved) = self.owners.get(position) {
+            self.table[moved].position = Some(position);
+        }
+        Some(self.values.swap_remove(position))
+    }
+
+    /// All live values, with no holes.
+    pub fn as_slice(&self) -> &[T] {
+        &self.values
+    }
+
+    /// All live values, mutably. Values can be changed in place but not moved, so every
+    /// handle keeps pointing at the same value.
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        &mut self.values
+    }
+
+    /// Iterate over live values in slice order, with their handles.
+    pub fn iter(&self) -> impl Iterator<Item = (Handle<T, G>, &T)> {
+        self.owners.iter().zip(&self.values).map(|(&idx, val)| {
+            let generation = self.table[idx].generation;
+            (Handle::issued(self.id, idx, generation), val)
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Where `h`'s value sits in `values`, if `h` is still valid.
+    fn position(&self, h: Handle<T, G>) -> Option<usize> {
+        if !h.issued_by(self.id) {
+            return None;
+        }
+        let entry = self.table.get(h.idx)?;
+        if entry.generation != h.generation {
+            return None;
+        }
+        entry.position
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slice_stays_dense() {
+        let mut vec = DenseDynVec::new();
+        let handles: Vec<_> = (0..5).map(|i| vec.insert(i * 10)).collect();
+        assert_eq!(vec.as_slice(), [0, 10, 20, 30, 40]);
+
+        // The last value fills the hole, and its handle follows it
+        assert_eq!(vec.remove(handles[1]), Some(10));
+        assert_eq!(vec.as_slice(), [0, 40, 20, 30]);
+        assert_eq!(vec.get(handles[4]), Some(&40));
+        assert_eq!(vec.remove(handles[1]), None);
+
+        // The freed table entry is reused with a new generation
+        let new = vec.insert(50);
+        assert_eq!(new.idx, handles[1].idx);
+        assert!(!vec.contains(handles[1]));
+        assert_eq!(vec.as_slice(), [0, 40, 20, 30, 50]);
+
+        for val in vec.as_mut_slice() {
+            *val += 1;
+        }
+        assert_eq!(vec.get(new), Some(&51));
+        let iterated: Vec<_> = vec.iter().map(|(h, &val)| (h.idx, val)).collect();
+        assert_eq!(iterated, [(0, 1), (4, 41), (2, 21), (3, 31), (1, 51)]);
+
+        // Removing the last value moves nothing
+        assert_eq!(vec.remove(new), Some(51));
+        assert_eq!(vec.len(), 4);
+        assert!(handles.iter().skip(2).all(|&h| vec.contains(h)));
+    }
+}
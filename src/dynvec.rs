@@ -1,37 +1,682 @@
 /// Give each element a generation counter. A handle is just {index, generation}.
 /// Any operation that might invalidate contents (remove, replace-with-different-type, clear, compaction) bumps the generation. Using a handle after that fails to upgrade.
-use std::mem;
+use crate::weak::Elem;
+use alloc::boxed::Box;
+use alloc::collections::{BinaryHeap, TryReserveError};
+use alloc::vec::Vec;
+use core::cmp::{Ordering, Reverse};
+use core::fmt;
+use core::hash::{Hash, Hasher};
+use core::marker::PhantomData;
+use core::mem;
+use core::num::{NonZeroU16, NonZeroU32, NonZeroU64};
+use core::ops::{Index, IndexMut};
+use core::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
+
+/// Map type returned by handle-remapping APIs such as `compact`.
+/// Always a `BTreeMap` from `alloc`, so enabling `std` elsewhere in the build can't change it.
+pub type HandleMap<K, V> = alloc::collections::BTreeMap<K, V>;
+
+/// Old-to-new handle map produced by `compact`.
+pub type Remap<T, G = NonZeroU32> = HandleMap<Handle<T, G>, Handle<T, G>>;
+
+/// `(old, new)` handles of an element that moved to another slot, e.g. in `swap_remove_compact`.
+pub type Moved<T, G = NonZeroU32> = (Handle<T, G>, Handle<T, G>);
 
 /// A handle to a slot in the vector.
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
-pub struct Handle {
+/// Generations are never zero, so `Option<Handle>` is the same size as `Handle`.
+/// The type parameter ties a handle to the arena it came from: `DynVec<T>` only accepts
+/// `Handle<T>`. Use `erase`/`cast` when a type-erased handle is genuinely needed.
+/// `G` is the generation width and must match the arena's.
+/// Each issued handle also records which arena issued it, so it never resolves against an
+/// unrelated arena even when index and generation happen to line up. Handles rebuilt with
+/// `new`, `unpack` or deserialization are unbound and resolve against any arena.
+/// The arena id only guards lookups: equality, hashing and ordering look at index and
+/// generation alone, so handles from different arenas can compare equal.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(bound(
+        serialize = "G: serde::Serialize",
+        deserialize = "G: serde::Deserialize<'de>"
+    ))
+)]
+pub struct Handle<T = (), G = NonZeroU32> {
     pub idx: usize,
-    pub generation: u32,
+    pub generation: G,
+    // Arena ids are process-local, so a deserialized handle comes back unbound
+    #[cfg_attr(feature = "serde", serde(skip))]
+    arena: u64,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    marker: PhantomData<fn() -> T>,
+}
+
+const _: () = assert!(size_of::<Option<Handle>>() == size_of::<Handle>());
+
+/// Arena id carried by hand-built handles: accepted by any arena of the right type.
+const UNBOUND_ARENA: u64 = 0;
+
+/// Index bits that `Handle::pack` gives the `dangling` handle.
+const PACKED_DANGLING: u32 = u32::MAX;
+
+impl<T, G: Generation> Handle<T, G> {
+    /// Rebuilds a handle from its parts, e.g. after storing them in an external format.
+    /// The result is not tied to an arena, so any `DynVec<T>` will resolve it, and it
+    /// compares equal to the arena-issued handle with the same parts (e.g. as a key into the
+    /// map `compact` returns).
+    pub fn new(idx: usize, generation: G) -> Self {
+        Self {
+            idx,
+            generation,
+            arena: UNBOUND_ARENA,
+            marker: PhantomData,
+        }
+    }
+
+    /// The null handle, for formats that can't hold an `Option<Handle>` (e.g. C structs or
+    /// flat arrays of handles). Its index is `usize::MAX`, which no arena ever reaches, so
+    /// it never resolves: `get` and friends report it as out of bounds.
+    pub fn dangling() -> Self {
+        Self::new(usize::MAX, G::first())
+    }
+
+    /// Whether this is the `dangling` sentinel. Only the index is compared.
+    pub fn is_dangling(&self) -> bool {
+        self.idx == usize::MAX
+    }
+
+    /// A handle issued by the arena with id `arena`.
+    pub(crate) fn issued(arena: u64, idx: usize, generation: G) -> Self {
+        Self {
+            arena,
+            ..Self::new(idx, generation)
+        }
+    }
+
+    /// Whether the arena with id `arena` may resolve this handle.
+    pub(crate) fn issued_by(&self, arena: u64) -> bool {
+        self.arena == arena || self.arena == UNBOUND_ARENA
+    }
+
+    pub fn idx(&self) -> usize {
+        self.idx
+    }
+
+    pub fn generation(&self) -> G {
+        self.generation
+    }
+
+    /// Drops the element type, e.g. to store handles to different element types together.
+    /// Handles from different arenas can still compare equal, so key such a collection by
+    /// arena as well, e.g. with `(arena_key, handle)` pairs.
+    pub fn erase(self) -> Handle<(), G> {
+        self.cast()
+    }
+
+    /// Reinterprets the handle for another element type; nothing checks that this is correct.
+    pub fn cast<U>(self) -> Handle<U, G> {
+        Handle::issued(self.arena, self.idx, self.generation)
+    }
+}
+
+impl<T> Handle<T> {
+    /// Encodes the handle as one `u64`, e.g. for FFI or a protobuf `fixed64`: `idx` in the
+    /// low 32 bits, `generation` in the high 32 bits. The `dangling` handle gets index bits
+    /// `u32::MAX`, so only indices below `u32::MAX` fit; larger ones trip a debug assertion
+    /// and are truncated to their low 32 bits in release builds.
+    /// The arena id is not encoded.
+    pub fn pack(self) -> u64 {
+        let idx = if self.is_dangling() {
+            PACKED_DANGLING
+        } else {
+            debug_assert!(
+                self.idx < PACKED_DANGLING as usize,
+                "handle index {} does not fit in 32 bits",
+                self.idx
+            );
+            self.idx as u32
+        };
+        (u64::from(self.generation.get()) << 32) | u64::from(idx)
+    }
+
+    /// Decodes a `pack`ed handle; `None` if the generation bits are zero, which `pack` never
+    /// produces. The result is unbound, like one from `Handle::new`, and equals the packed handle
+    /// (a packed `dangling` handle comes back dangling).
+    pub fn unpack(bits: u64) -> Option<Self> {
+        let generation = NonZeroU32::new((bits >> 32) as u32)?;
+        let idx = match bits as u32 {
+            PACKED_DANGLING => usize::MAX,
+            idx => idx as usize,
+        };
+        Some(Self::new(idx, generation))
+    }
+}
+
+// Manual impls so that `Handle<T>` is `Copy`/`Eq`/`Hash` regardless of `T`. The arena id is
+// left out of `Eq`/`Hash`/`Ord`, so rebuilt and unpacked handles equal the issued ones.
+impl<T, G: Generation> Clone for Handle<T, G> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T, G: Generation> Copy for Handle<T, G> {}
+
+impl<T, G: Generation> PartialEq for Handle<T, G> {
+    fn eq(&self, other: &Self) -> bool {
+        self.idx == other.idx && self.generation == other.generation
+    }
+}
+
+impl<T, G: Generation> Eq for Handle<T, G> {}
+
+impl<T, G: Generation> Hash for Handle<T, G> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.idx.hash(state);
+        self.generation.hash(state);
+    }
+}
+
+// Ordered by index, then generation, so handles can key a `BTreeMap` and sort deterministically.
+impl<T, G: Generation> PartialOrd for Handle<T, G> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T, G: Generation> Ord for Handle<T, G> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.idx, self.generation).cmp(&(other.idx, other.generation))
+    }
+}
+
+impl<T, G: Generation> fmt::Debug for Handle<T, G> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Handle")
+            .field("idx", &self.idx)
+            .field("generation", &self.generation)
+            .field("arena", &self.arena)
+            .finish()
+    }
+}
+
+/// Index of the first set bit at or after `from`, scanning whole words at a time.
+fn next_occupied(occupied: &[u64], from: usize) -> Option<usize> {
+    let mut word = from / 64;
+    let mut bits = occupied.get(word)? & (!0u64 << (from % 64));
+    while bits == 0 {
+        word += 1;
+        bits = *occupied.get(word)?;
+    }
+    Some(word * 64 + bits.trailing_zeros() as usize)
+}
+
+/// Source of per-arena ids; starts past `UNBOUND_ARENA`.
+static NEXT_ARENA_ID: AtomicU64 = AtomicU64::new(UNBOUND_ARENA + 1);
+
+pub(crate) fn next_arena_id() -> u64 {
+    NEXT_ARENA_ID.fetch_add(1, AtomicOrdering::Relaxed)
+}
+
+/// A generation counter. Zero is never a valid generation, which keeps `Option<Handle>`
+/// niche-optimized; pick `NonZeroU16` to save memory or `NonZeroU64` to push retirement
+/// out of reach. `NonZeroU32` is the default.
+pub trait Generation: Copy + Ord + Hash + fmt::Debug {
+    /// The generation a brand-new slot starts at.
+    fn first() -> Self;
+    /// The next generation, or `None` once the counter is exhausted.
+    fn bump(self) -> Option<Self>;
+    /// How much of the counter's range is used up: 0.0 at `first`, 1.0 when exhausted.
+    fn spent(self) -> f64;
+}
+
+macro_rules! impl_generation {
+    ($($t:ty),*) => {
+        $(impl Generation for $t {
+            fn first() -> Self {
+                <$t>::MIN
+            }
+            fn bump(self) -> Option<Self> {
+                self.checked_add(1)
+            }
+            fn spent(self) -> f64 {
+                (self.get() - 1) as f64 / (<$t>::MAX.get() - 1) as f64
+            }
+        })*
+    };
+}
+
+impl_generation!(NonZeroU16, NonZeroU32, NonZeroU64);
+
+/// Why a handle failed to resolve, as reported by `DynVec::validate` and the fallible
+/// mutators (`replace`, `swap`, `map_invalidate`, ...).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HandleError<G = NonZeroU32> {
+    /// The handle was issued by a different arena.
+    ForeignArena,
+    /// The index is past the end of the arena.
+    OutOfBounds,
+    /// The slot has moved on since the handle was issued (the element was removed or replaced).
+    GenerationMismatch { current: G, handle: G },
+    /// The generation matches but the slot holds no value.
+    Vacant,
+    /// Two handles that must be distinct point at the same slot, e.g. in `swap_values`.
+    SameSlot,
+}
+
+impl<G: fmt::Debug> fmt::Display for HandleError<G> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HandleError::ForeignArena => f.write_str("handle belongs to a different arena"),
+            HandleError::OutOfBounds => f.write_str("handle index is out of bounds"),
+            HandleError::GenerationMismatch { current, handle } => write!(
+                f,
+                "stale handle: slot is at generation {:?}, handle has {:?}",
+                current, handle
+            ),
+            HandleError::Vacant => f.write_str("handle points at an empty slot"),
+            HandleError::SameSlot => f.write_str("handles point at the same slot"),
+        }
+    }
 }
 
+impl<G: fmt::Debug> core::error::Error for HandleError<G> {}
+
 /// A vector of elements with generational semantics.
 #[derive(Debug)]
-struct Slot<T> {
-    generation: u32,
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct Slot<T, G> {
+    generation: G,
     val: Option<T>,
 }
 
+impl<T: Clone, G: Clone> Clone for Slot<T, G> {
+    fn clone(&self) -> Self {
+        Self {
+            generation: self.generation.clone(),
+            val: self.val.clone(),
+        }
+    }
+
+    fn clone_from(&mut self, source: &Self) {
+        self.generation.clone_from(&source.generation);
+        self.val.clone_from(&source.val);
+    }
+}
+
 /// Free contains a list of indices of slots that are free.
 /// The free list is a vector of indices, so that we can use Vec::swap_remove.
+/// Which free slot `insert` reuses is set by the `FreePolicy`; the next one is always `free`'s last.
+/// A slot whose generation is exhausted is retired instead: it stays empty and never
+/// goes back on the free list, so a wrapped generation can't resurrect an old handle.
+/// `reserved` lists slots handed out by `reserve_handles` that are still waiting for `fill`:
+/// empty, but neither free nor retired.
+/// `occupied` is a bitmap with one bit per slot, set while the slot holds a value. `val` stays
+/// the source of truth; the bitmap only lets iteration skip runs of empty slots a word at a time.
+/// Every arena gets a process-unique `id`, stamped into the handles it issues and checked on lookup.
+/// Cloning copies generations, the free list and the id exactly, so handles stay valid in the clone.
+/// With the `serde` feature, the slot layout is (de)serialized verbatim for the same reason.
+/// The id is not: ids are only unique within a process, so a loaded arena gets a fresh one.
+/// Handles saved along with it come back unbound and resolve as before, but handles kept in
+/// memory across a save and load still carry the old id and must be rebuilt with `Handle::new`.
+/// Auto-compaction settings belong to the arena instance: clones and deserialized arenas
+/// start with it off, since the hook that fixes up handles can't be carried over.
 #[derive(Debug)]
-pub struct DynVec<T> {
-    slots: Vec<Slot<T>>,
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+// Bounds spelled out, since the skipped compaction hook would otherwise demand
+// `T: Default, G: Default`. Loading goes through `DynVecData`, which is checked first.
+#[cfg_attr(
+    feature = "serde",
+    serde(
+        bound(
+            serialize = "T: serde::Serialize, G: serde::Serialize",
+            deserialize = "T: serde::Deserialize<'de>, G: Generation + serde::Deserialize<'de>"
+        ),
+        try_from = "DynVecData<T, G>"
+    )
+)]
+pub struct DynVec<T, G = NonZeroU32> {
+    slots: Vec<Slot<T, G>>,
+    free: FreeList,
+    // Derived from `slots`, `free` and `reserved`, so it is rebuilt on load rather than saved
+    #[cfg_attr(feature = "serde", serde(skip))]
+    occupied: Vec<u64>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    retired: usize,
+    reserved: Vec<usize>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    id: u64,
+    /// Insertion order as `(idx, generation)`, only kept once `track_order` is called.
+    order: Option<Vec<(usize, G)>>,
+    /// Fixed at construction, as it decides how `free` is stored.
+    policy: FreePolicy,
+    /// Generation that new slots start at, once `shrink_to_fit` or `compact` has trimmed
+    /// used indices.
+    floor: Option<G>,
+    /// Free-to-live ratio past which `remove` compacts; see `set_auto_compact`.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    auto_compact: Option<f64>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    on_compact: Option<CompactHook<T, G>>,
+}
+
+/// The saved parts of a `DynVec`. Fields added after the first serialized format default,
+/// so older data still loads.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+#[serde(bound(deserialize = "T: serde::Deserialize<'de>, G: serde::Deserialize<'de>"))]
+struct DynVecData<T, G> {
+    slots: Vec<Slot<T, G>>,
     free: Vec<usize>,
+    #[serde(default)]
+    reserved: Vec<usize>,
+    #[serde(default)]
+    order: Option<Vec<(usize, G)>>,
+    #[serde(default)]
+    policy: FreePolicy,
+    #[serde(default)]
+    floor: Option<G>,
+}
+
+/// Rejects a free or reserved list that names a missing, live or already listed slot, which
+/// would otherwise panic or hand out a slot twice later on.
+#[cfg(feature = "serde")]
+impl<T, G: Generation> TryFrom<DynVecData<T, G>> for DynVec<T, G> {
+    type Error = alloc::string::String;
+
+    fn try_from(data: DynVecData<T, G>) -> Result<Self, Self::Error> {
+        let mut listed = alloc::vec![false; data.slots.len()];
+        for (list, indices) in [("free", &data.free), ("reserved", &data.reserved)] {
+            for &idx in indices {
+                match data.slots.get(idx) {
+                    None => return Err(alloc::format!("{list} slot {idx} is out of bounds")),
+                    Some(slot) if slot.val.is_some() => {
+                        return Err(alloc::format!("{list} slot {idx} holds a value"));
+                    }
+                    Some(_) if listed[idx] => {
+                        return Err(alloc::format!("slot {idx} is listed twice"));
+                    }
+                    Some(_) => listed[idx] = true,
+                }
+            }
+        }
+        let mut vec = Self {
+            free: FreeList::from_vec(data.policy, data.free),
+            reserved: data.reserved,
+            policy: data.policy,
+            floor: data.floor,
+            ..Self::default()
+        };
+        for (idx, slot) in data.slots.iter().enumerate() {
+            if slot.val.is_some() {
+                vec.set_occupied(idx, true);
+            } else if !listed[idx] {
+                // Neither free nor reserved: its generations are used up
+                vec.retired += 1;
+            }
+        }
+        vec.slots = data.slots;
+        // Entries for slots that are gone are skipped by `ordered` anyway
+        vec.order = data.order.map(|order| {
+            order
+                .into_iter()
+                .filter(|&(idx, _)| idx < vec.slots.len())
+                .collect()
+        });
+        Ok(vec)
+    }
+}
+
+/// Callback told about the remap whenever auto-compaction runs.
+pub struct CompactHook<T, G>(Box<dyn OnCompact<T, G>>);
+
+/// Bounds for a compaction callback; `Send + Sync` keeps the arena shareable across threads.
+trait OnCompact<T, G>: FnMut(&Remap<T, G>) + Send + Sync {}
+
+impl<T, G, F: FnMut(&Remap<T, G>) + Send + Sync> OnCompact<T, G> for F {}
+
+impl<T, G> fmt::Debug for CompactHook<T, G> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("CompactHook(..)")
+    }
+}
+
+/// Collects construction options for a `DynVec`, so they don't pile up as constructors.
+#[derive(Debug)]
+pub struct DynVecBuilder<T, G = NonZeroU32> {
+    capacity: usize,
+    policy: FreePolicy,
+    auto_compact: Option<f64>,
+    track_arena_id: bool,
+    marker: PhantomData<fn() -> DynVec<T, G>>,
+}
+
+impl<T, G> Default for DynVecBuilder<T, G> {
+    fn default() -> Self {
+        Self {
+            capacity: 0,
+            policy: FreePolicy::Lifo,
+            auto_compact: None,
+            track_arena_id: true,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T, G: Generation> DynVecBuilder<T, G> {
+    /// Pre-sizes the backing storage, like `with_capacity`.
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Which freed slot gets reused first, like `with_free_policy`.
+    pub fn free_policy(mut self, policy: FreePolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Turns on auto-compaction, like `set_auto_compact`; see there for the caveats.
+    pub fn auto_compact_ratio(mut self, ratio: f64) -> Self {
+        self.auto_compact = Some(ratio);
+        self
+    }
+
+    /// With `false`, the arena stamps no id into its handles and accepts handles from any
+    /// arena, trading the cross-arena check for interchangeable handles. On by default.
+    pub fn track_arena_id(mut self, track: bool) -> Self {
+        self.track_arena_id = track;
+        self
+    }
+
+    pub fn build(self) -> DynVec<T, G> {
+        let mut vec = DynVec {
+            free: FreeList::new(self.policy),
+            policy: self.policy,
+            auto_compact: self.auto_compact,
+            ..DynVec::default()
+        };
+        vec.slots.reserve(self.capacity);
+        if !self.track_arena_id {
+            vec.id = UNBOUND_ARENA;
+        }
+        vec
+    }
+}
+
+/// Which freed slot `insert` reuses first.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FreePolicy {
+    /// The most recently freed slot (a stack). Cheapest, but churn concentrates on a few
+    /// hot slots, whose generations climb fastest.
+    #[default]
+    Lifo,
+    /// The lowest free index. Spreads generation bumps across slots and keeps the live
+    /// elements packed at the front, which leaves less for `shrink_to_fit` and `compact`
+    /// to do. The free list is kept as a min-heap, so each removal costs O(log free slots).
+    LowestIndex,
+}
+
+/// Indices of freed slots, stored the way the arena's `FreePolicy` reuses them.
+#[derive(Clone, Debug)]
+enum FreeList {
+    /// A stack: the most recently freed index is on top.
+    Lifo(Vec<usize>),
+    /// A min-heap, so the lowest index is on top.
+    LowestIndex(BinaryHeap<Reverse<usize>>),
+}
+
+impl FreeList {
+    fn new(policy: FreePolicy) -> Self {
+        Self::from_vec(policy, Vec::new())
+    }
+
+    fn from_vec(policy: FreePolicy, free: Vec<usize>) -> Self {
+        match policy {
+            FreePolicy::Lifo => Self::Lifo(free),
+            FreePolicy::LowestIndex => Self::LowestIndex(free.into_iter().map(Reverse).collect()),
+        }
+    }
+
+    fn push(&mut self, idx: usize) {
+        match self {
+            Self::Lifo(free) => free.push(idx),
+            Self::LowestIndex(free) => free.push(Reverse(idx)),
+        }
+    }
+
+    fn pop(&mut self) -> Option<usize> {
+        match self {
+            Self::Lifo(free) => free.pop(),
+            Self::LowestIndex(free) => free.pop().map(|Reverse(idx)| idx),
+        }
+    }
+
+    /// The index `pop` would return.
+    fn peek(&self) -> Option<usize> {
+        match self {
+            Self::Lifo(free) => free.last().copied(),
+            Self::LowestIndex(free) => free.peek().map(|&Reverse(idx)| idx),
+        }
+    }
+
+    /// Takes `idx` off the list if it is on it; returns whether it was. O(free slots) unless
+    /// `idx` is on top.
+    fn remove(&mut self, idx: usize) -> bool {
+        // Usually the slot was just freed, and is on top
+        if self.peek() == Some(idx) {
+            self.pop();
+            return true;
+        }
+        let len = self.len();
+        match self {
+            Self::Lifo(free) => free.retain(|&free| free != idx),
+            Self::LowestIndex(free) => free.retain(|&Reverse(free)| free != idx),
+        }
+        self.len() != len
+    }
+
+    fn contains(&self, idx: usize) -> bool {
+        match self {
+            Self::Lifo(free) => free.contains(&idx),
+            Self::LowestIndex(free) => free.iter().any(|&Reverse(free)| free == idx),
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Self::Lifo(free) => free.len(),
+            Self::LowestIndex(free) => free.len(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn clear(&mut self) {
+        match self {
+            Self::Lifo(free) => free.clear(),
+            Self::LowestIndex(free) => free.clear(),
+        }
+    }
+
+    fn capacity(&self) -> usize {
+        match self {
+            Self::Lifo(free) => free.capacity(),
+            Self::LowestIndex(free) => free.capacity(),
+        }
+    }
+
+    fn shrink_to_fit(&mut self) {
+        match self {
+            Self::Lifo(free) => free.shrink_to_fit(),
+            Self::LowestIndex(free) => free.shrink_to_fit(),
+        }
+    }
+}
+
+/// Saved as a plain list of indices; `DynVecData` rebuilds the heap from the policy.
+#[cfg(feature = "serde")]
+impl serde::Serialize for FreeList {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::Lifo(free) => free.serialize(serializer),
+            Self::LowestIndex(free) => serializer.collect_seq(free.iter().map(|&Reverse(idx)| idx)),
+        }
+    }
 }
 
 /// Initalize a DynVec with a default value.
-impl<T> Default for DynVec<T> {
+impl<T, G> Default for DynVec<T, G> {
     fn default() -> Self {
         Self {
             slots: Vec::new(),
-            free: Vec::new(),
+            free: FreeList::new(FreePolicy::Lifo),
+            occupied: Vec::new(),
+            retired: 0,
+            reserved: Vec::new(),
+            id: next_arena_id(),
+            order: None,
+            policy: FreePolicy::Lifo,
+            floor: None,
+            auto_compact: None,
+            on_compact: None,
+        }
+    }
+}
+
+impl<T: Clone, G: Clone> Clone for DynVec<T, G> {
+    fn clone(&self) -> Self {
+        Self {
+            slots: self.slots.clone(),
+            free: self.free.clone(),
+            occupied: self.occupied.clone(),
+            retired: self.retired,
+            reserved: self.reserved.clone(),
+            id: self.id,
+            order: self.order.clone(),
+            policy: self.policy,
+            floor: self.floor.clone(),
+            auto_compact: None,
+            on_compact: None,
         }
     }
+
+    /// Overwrites `self` in place, reusing its allocations (and those of values that
+    /// implement `clone_from`), e.g. to re-snapshot an arena every frame.
+    fn clone_from(&mut self, source: &Self) {
+        self.slots.clone_from(&source.slots);
+        self.free.clone_from(&source.free);
+        self.occupied.clone_from(&source.occupied);
+        self.retired = source.retired;
+        self.reserved.clone_from(&source.reserved);
+        self.id = source.id;
+        self.order.clone_from(&source.order);
+        self.policy = source.policy;
+        self.floor.clone_from(&source.floor);
+    }
 }
 
 impl<T> DynVec<T> {
@@ -39,113 +684,2617 @@ impl<T> DynVec<T> {
         Self::default()
     }
 
-    /// Inserts a new item: bumps generation and returns a handle.
-    pub fn insert(&mut self, value: T) -> Handle {
-        if let Some(idx) = self.free.pop() {
-            // reuse a slot
-            let generation = self.slots[idx].generation; // save the old generation
-            self.slots[idx].val = Some(value); // set the new value
-            Handle { idx, generation } // return the updated handle
-        } else {
-            // no free slots, so we need to add a new slot
-            let idx = self.slots.len(); // get the index of the new slot
-            self.slots.push(Slot {
-                generation: 0,
-                val: Some(value),
-            });
-            Handle { idx, generation: 0 }
+    /// Pre-sizes the backing storage for `capacity` elements.
+    /// No slots are created, so `len` is unchanged; this only affects allocation.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            slots: Vec::with_capacity(capacity),
+            free: FreeList::new(FreePolicy::Lifo),
+            occupied: Vec::new(),
+            retired: 0,
+            reserved: Vec::new(),
+            id: next_arena_id(),
+            order: None,
+            policy: FreePolicy::Lifo,
+            floor: None,
+            auto_compact: None,
+            on_compact: None,
         }
     }
 
-    /// Reassigns the slot (e.g., "Variant changed type"): bumps generation.
-    pub fn replace(&mut self, h: Handle, value: T) -> Result<Handle, ()> {
-        let slot = self.slots.get_mut(h.idx).ok_or(())?; // get a mutable reference to the slot
-        if slot.generation != h.generation || slot.val.is_none() {
-            return Err(()); // generation mismatch or slot is not initalized
+    /// An empty arena that reuses freed slots according to `policy`.
+    pub fn with_free_policy(policy: FreePolicy) -> Self {
+        Self {
+            free: FreeList::new(policy),
+            policy,
+            ..Self::default()
         }
-        slot.generation = slot.generation.wrapping_add(1); // bump the generation
-        slot.val = Some(value);
-        Ok(Handle {
-            idx: h.idx,
-            generation: slot.generation,
-        })
     }
 
-    /// Get a reference to the value of the slot.
-    pub fn get(&self, h: Handle) -> Option<&T> {
-        let slot = self.slots.get(h.idx)?;
-        (slot.generation == h.generation)
-            .then(|| slot.val.as_ref())
-            .flatten()
+    /// Starts configuring an arena; `DynVec::builder().build()` is the same as `new()`.
+    /// Other generation widths go through `DynVecBuilder::<T, G>::default()`.
+    pub fn builder() -> DynVecBuilder<T> {
+        DynVecBuilder::default()
     }
+}
 
-    /// Get a mutable reference to the value of the slot.
-    pub fn get_mut(&mut self, h: Handle) -> Option<&mut T> {
-        let slot = self.slots.get_mut(h.idx)?;
-        (slot.generation == h.generation)
-            .then(|| slot.val.as_mut())
-            .flatten()
+/// Other generation widths are picked through the type, e.g. `DynVec::<T, NonZeroU64>::default()`.
+impl<T, G: Generation> DynVec<T, G> {
+    /// Builds an arena where element `i` of `v` lives in slot `i` at the first generation,
+    /// so existing `usize` indices can be turned into handles with `handle_for`.
+    pub fn from_vec(v: Vec<T>) -> Self {
+        let mut vec = Self {
+            slots: v
+                .into_iter()
+                .map(|val| Slot {
+                    generation: G::first(),
+                    val: Some(val),
+                })
+                .collect(),
+            ..Self::default()
+        };
+        for idx in 0..vec.slots.len() {
+            vec.set_occupied(idx, true);
+        }
+        vec
     }
 
-    #[allow(dead_code)]
-    /// Deletes the item: bumps generation and frees the slot.
-    pub fn remove(&mut self, h: Handle) -> Option<T> {
-        let slot = self.slots.get_mut(h.idx)?;
-        if slot.generation != h.generation || slot.val.is_none() {
-            return None;
-        }
-        let old = slot.val.take();
-        slot.generation = slot.generation.wrapping_add(1);
-        self.free.push(h.idx);
-        old
+    /// The handle `from_vec` gave to index `idx`. It only resolves while that slot has not
+    /// been removed or replaced since; after that it is stale like any other old handle.
+    pub fn handle_for(&self, idx: usize) -> Handle<T, G> {
+        Handle::issued(self.id, idx, G::first())
     }
 
-    #[allow(dead_code)]
-    /// Bulk mutation (e.g., clear or reallocate): invalidate *all* contents.
-    pub fn clear(&mut self) {
-        for (i, slot) in self.slots.iter_mut().enumerate() {
-            if slot.val.is_some() {
-                slot.val = None;
-                slot.generation = slot.generation.wrapping_add(1);
-                self.free.push(i);
+    /// Consumes the arena into its live values, in slot order. After removals and slot
+    /// reuse that is not necessarily insertion order; see `ordered` for that.
+    ///
+    /// This and `to_vec` are the way to hand the values to slice-based APIs: each value is
+    /// stored next to its slot's generation, so not even a hole-free arena has a `&[T]` to lend.
+    /// `DenseDynVec` keeps its values packed, and lends them with `as_slice`.
+    pub fn into_vec(self) -> Vec<T> {
+        self.slots.into_iter().filter_map(|slot| slot.val).collect()
+    }
+
+    /// Borrowing `into_vec`: clones the live values, in slot order.
+    pub fn to_vec(&self) -> Vec<T>
+    where
+        T: Clone,
+    {
+        self.values().cloned().collect()
+    }
+
+    /// Reserves room for at least `additional` more slots.
+    /// Handles are index-based, so growing the backing `Vec` never invalidates them.
+    ///
+    /// Growth policy: freed slots are always reused before new ones are appended, and
+    /// appending grows `slots` the way `Vec::push` does (geometrically, amortized O(1)).
+    pub fn reserve(&mut self, additional: usize) {
+        self.slots.reserve(additional);
+    }
+
+    /// Like `reserve`, but without the geometric over-allocation, for predictable bursts.
+    pub fn reserve_exact(&mut self, additional: usize) {
+        self.slots.reserve_exact(additional);
+    }
+
+    /// Fallible `reserve`: reports allocation failure instead of aborting, for environments
+    /// where running out of memory must be handled. Also reserves the occupancy bitmap words
+    /// the new slots will need.
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), TryReserveError> {
+        self.slots.try_reserve(additional)?;
+        let words = self.slots.len().saturating_add(additional).div_ceil(64);
+        self.occupied
+            .try_reserve(words.saturating_sub(self.occupied.len()))
+    }
+
+    /// Get the number of slots the backing storage can hold without reallocating.
+    pub fn capacity(&self) -> usize {
+        self.slots.capacity()
+    }
+
+    /// Drops the freed slots past the last live element and releases spare capacity.
+    /// Interior freed slots are kept, since their indices (and generations) are still in use.
+    /// Trailing retired or reserved slots stop the trimming, as they must stay put.
+    ///
+    /// A trimmed index does not start over at the first generation when it is reused: every
+    /// slot created afterwards starts at the highest generation trimmed so far, so handles to
+    /// the trimmed slots stay stale.
+    pub fn shrink_to_fit(&mut self) {
+        while let Some(idx) = self.slots.len().checked_sub(1)
+            && self.free.remove(idx)
+        {
+            // A freed slot's generation was bumped past its last handle, so it is unused yet
+            if let Some(slot) = self.slots.pop() {
+                self.floor = Some(self.fresh_generation().max(slot.generation));
             }
         }
+        let end = self.slots.len();
+        // Entries for trimmed slots are stale and would only pile up
+        if let Some(order) = &mut self.order {
+            order.retain(|&(idx, _)| idx < end);
+        }
+        // The trimmed slots were empty, so their bits are already clear
+        self.occupied.truncate(end.div_ceil(64));
+        self.slots.shrink_to_fit();
+        self.free.shrink_to_fit();
+        self.occupied.shrink_to_fit();
     }
 
-    #[allow(dead_code)]
-    /// Swap-without-borrowing-T: contents remain valid (no gen bump).
-    pub fn swap(&mut self, a: Handle, b: Handle) -> Result<(), ()> {
-        let (sa, sb) = {
-            let sa = self.slots.get(a.idx).ok_or(())?;
-            let sb = self.slots.get(b.idx).ok_or(())?;
-            if sa.generation != a.generation || sb.generation != b.generation {
-                return Err(());
-            }
-            (a.idx, b.idx)
-        };
-        self.slots.swap(sa, sb);
-        Ok(())
+    /// Inserts a new item: bumps generation and returns a handle.
+    pub fn insert(&mut self, value: T) -> Handle<T, G> {
+        self.insert_with(|| value)
     }
 
-    #[allow(dead_code)]
-    /// "Type change" helper for Variant-like containers.
-    pub fn map_invalidate<F>(&mut self, h: Handle, f: F) -> Result<(), ()>
+    /// Like `insert`, but only builds the value once a slot has been picked.
+    pub fn insert_with<F>(&mut self, f: F) -> Handle<T, G>
     where
-        F: FnOnce(Option<T>) -> Option<T>,
+        F: FnOnce() -> T,
     {
-        let slot = self.slots.get_mut(h.idx).ok_or(())?;
-        if slot.generation != h.generation {
-            return Err(());
+        let h = self.insert_slot(f);
+        if let Some(order) = &mut self.order {
+            order.push((h.idx, h.generation));
         }
-        let new_val = f(mem::take(&mut slot.val));
-        // Changing contents' identity => bump gen
-        slot.generation = slot.generation.wrapping_add(1);
-        slot.val = new_val;
-        Ok(())
+        self.prune_order();
+        h
     }
 
-    /// Get the number of slots (for debugging/testing purposes)
-    pub fn len(&self) -> usize {
-        self.slots.len()
+    /// The generation a newly created slot starts at.
+    fn fresh_generation(&self) -> G {
+        self.floor.unwrap_or(G::first())
+    }
+
+    /// Places a value in a free or new slot, without touching the insertion order.
+    fn insert_slot<F>(&mut self, f: F) -> Handle<T, G>
+    where
+        F: FnOnce() -> T,
+    {
+        if let Some(idx) = self.free.peek() {
+            // reuse a slot; it stays on the free list until the value exists, in case `f` panics
+            let generation = self.slots[idx].generation; // save the old generation
+            self.slots[idx].val = Some(f()); // set the new value
+            self.free.pop();
+            self.set_occupied(idx, true);
+            Handle::issued(self.id, idx, generation) // return the updated handle
+        } else {
+            // no free slots, so we need to add a new slot
+            let idx = self.slots.len(); // get the index of the new slot
+            let generation = self.fresh_generation();
+            self.slots.push(Slot {
+                generation,
+                val: Some(f()),
+            });
+            self.set_occupied(idx, true);
+            Handle::issued(self.id, idx, generation)
+        }
+    }
+
+    /// Interning helper: the handle of the first live element `matches` accepts, or of a
+    /// freshly inserted `make()` if there is none. This is a linear scan, meant for small arenas.
+    pub fn get_or_insert_with<F, M>(&mut self, matches: F, make: M) -> Handle<T, G>
+    where
+        F: Fn(&T) -> bool,
+        M: FnOnce() -> T,
+    {
+        let found = self.find_handle(matches);
+        found.unwrap_or_else(|| self.insert_with(make))
+    }
+
+    /// Duplicates the element behind `h` (e.g. "duplicate node" in an editor) and returns
+    /// the copy's handle; `None` if `h` is stale. The original and its handle are untouched.
+    pub fn clone_slot(&mut self, h: Handle<T, G>) -> Option<Handle<T, G>>
+    where
+        T: Clone,
+    {
+        let value = self.get(h)?.clone();
+        Some(self.insert(value))
+    }
+
+    /// The first live element (in slot order) that `pred` accepts, with its handle.
+    /// A linear scan, for small arenas without an external index.
+    pub fn find<F>(&self, pred: F) -> Option<(Handle<T, G>, &T)>
+    where
+        F: Fn(&T) -> bool,
+    {
+        self.iter().find(|(_, val)| pred(val))
+    }
+
+    /// Like `find`, but only the handle.
+    pub fn find_handle<F>(&self, pred: F) -> Option<Handle<T, G>>
+    where
+        F: Fn(&T) -> bool,
+    {
+        self.find(pred).map(|(h, _)| h)
+    }
+
+    /// Every live element `pred` accepts, in slot order.
+    pub fn find_all<F>(&self, pred: F) -> impl Iterator<Item = (Handle<T, G>, &T)>
+    where
+        F: Fn(&T) -> bool,
+    {
+        self.iter().filter(move |(_, val)| pred(val))
+    }
+
+    /// Upsert for arenas behind an external key-to-handle map: the element `h` points at,
+    /// or a freshly inserted `default()` when `h` is `None` or stale. The returned handle is
+    /// the one to store back in the map.
+    pub fn get_mut_or_insert<F>(
+        &mut self,
+        h: Option<Handle<T, G>>,
+        default: F,
+    ) -> (Handle<T, G>, &mut T)
+    where
+        F: FnOnce() -> T,
+    {
+        let h = match h {
+            Some(h) if self.contains(h) => h,
+            _ => self.insert_with(default),
+        };
+        (h, &mut self[h])
+    }
+
+    /// Inserts only while fewer than `max` elements are live; otherwise hands `value` back.
+    /// Freed slots are reused as usual, so the cap bounds live elements, not past insertions.
+    pub fn try_insert(&mut self, value: T, max: usize) -> Result<Handle<T, G>, T> {
+        if self.len() >= max {
+            return Err(value);
+        }
+        Ok(self.insert(value))
+    }
+
+    /// Like `insert`, but hands `value` back instead of panicking or aborting when a new
+    /// slot cannot be allocated. Indices are plain `usize`, so the arena addresses as many
+    /// slots as its backing `Vec` can hold: `isize::MAX` bytes' worth.
+    pub fn try_insert_new(&mut self, value: T) -> Result<Handle<T, G>, T> {
+        if self.free.is_empty() && self.try_reserve(1).is_err() {
+            return Err(value);
+        }
+        if let Some(order) = &mut self.order
+            && order.try_reserve(1).is_err()
+        {
+            return Err(value);
+        }
+        Ok(self.insert(value))
+    }
+
+    /// Hands out `n` handles before their values exist, e.g. ids sent to clients ahead of
+    /// the entities. The slots stay empty (`get` returns `None`) until `fill` is called with
+    /// the exact handle; they are not on the free list, so `insert` never takes them.
+    pub fn reserve_handles(&mut self, n: usize) -> Vec<Handle<T, G>> {
+        (0..n)
+            .map(|_| {
+                let idx = self.free.pop().unwrap_or_else(|| {
+                    self.slots.push(Slot {
+                        generation: self.fresh_generation(),
+                        val: None,
+                    });
+                    self.slots.len() - 1
+                });
+                self.reserved.push(idx);
+                Handle::issued(self.id, idx, self.slots[idx].generation)
+            })
+            .collect()
+    }
+
+    /// Stores the value for a handle from `reserve_handles`. Hands `value` back if `h` is
+    /// not a pending reservation (already filled, stale, or never reserved).
+    pub fn fill(&mut self, h: Handle<T, G>, value: T) -> Result<(), T> {
+        let Some(pos) = self.reserved.iter().position(|&idx| idx == h.idx) else {
+            return Err(value);
+        };
+        let Some(slot) = self.slot_mut(h) else {
+            return Err(value);
+        };
+        slot.val = Some(value);
+        self.reserved.swap_remove(pos);
+        self.set_occupied(h.idx, true);
+        if let Some(order) = &mut self.order {
+            order.push((h.idx, h.generation));
+        }
+        Ok(())
+    }
+
+    /// Inserts every element (reusing freed slots first), returning the handles in order.
+    pub fn insert_many<I>(&mut self, iter: I) -> Vec<Handle<T, G>>
+    where
+        I: IntoIterator<Item = T>,
+    {
+        let iter = iter.into_iter();
+        self.reserve(iter.size_hint().0.saturating_sub(self.free.len()));
+        iter.map(|value| self.insert(value)).collect()
+    }
+
+    /// Moves every live element of `other` in (reusing freed slots first) and returns a map
+    /// from `other`'s handles to their new handles here, for fixing up cross-references.
+    pub fn merge(&mut self, other: DynVec<T, G>) -> Remap<T, G> {
+        self.reserve(other.len().saturating_sub(self.free.len()));
+        other
+            .into_iter()
+            .map(|(old, value)| (old, self.insert(value)))
+            .collect()
+    }
+
+    /// Reassigns the slot (e.g., "Variant changed type"): bumps generation.
+    #[must_use = "the old handle is dead after a successful replace, and a failed one changes nothing"]
+    pub fn replace(&mut self, h: Handle<T, G>, value: T) -> Result<Handle<T, G>, HandleError<G>> {
+        let slot = self.checked_slot_mut(h)?; // get a mutable reference to the slot
+        if slot.val.is_none() {
+            return Err(HandleError::Vacant); // slot is not initalized
+        }
+        let Some(generation) = slot.generation.bump() else {
+            // generation exhausted: retire the slot and move the value elsewhere
+            self.release(h.idx);
+            let new = self.insert_slot(|| value);
+            self.reorder(h, new);
+            return Ok(new);
+        };
+        slot.generation = generation; // bump the generation
+        slot.val = Some(value);
+        let new = Handle::issued(self.id, h.idx, generation);
+        self.reorder(h, new);
+        Ok(new)
+    }
+
+    /// `replace` with a value computed from the old one: the old handle dies and the
+    /// returned one sees `f(old)`. If `f` panics, the element is simply gone, as if removed.
+    #[must_use = "the old handle is dead after a successful replace, and a failed one changes nothing"]
+    pub fn replace_with<F>(&mut self, h: Handle<T, G>, f: F) -> Result<Handle<T, G>, HandleError<G>>
+    where
+        F: FnOnce(T) -> T,
+    {
+        let old = self
+            .checked_slot_mut(h)?
+            .val
+            .take()
+            .ok_or(HandleError::Vacant)?;
+        // Free the slot before running `f`, so a panic can't leave it half-emptied
+        self.release(h.idx);
+        let value = f(old);
+        let new = if self.free.remove(h.idx) {
+            // Take the same slot back; its generation was bumped by `release`
+            let slot = &mut self.slots[h.idx];
+            slot.val = Some(value);
+            self.set_occupied(h.idx, true);
+            Handle::issued(self.id, h.idx, self.slots[h.idx].generation)
+        } else {
+            // generation exhausted: the slot was retired, so the value moves elsewhere
+            self.insert_slot(|| value)
+        };
+        self.reorder(h, new);
+        Ok(new)
+    }
+
+    /// Overwrites the value but keeps the handle valid (no gen bump); returns the old value.
+    pub fn replace_in_place(&mut self, h: Handle<T, G>, value: T) -> Option<T> {
+        self.get_mut(h).map(|val| mem::replace(val, value))
+    }
+
+    /// `replace` or `replace_in_place`, decided by `changed(old, new)`: only an update that
+    /// changes the element's identity (e.g. an enum switching variants) bumps the generation.
+    /// Returns the handle to use from now on, which is `h` itself when nothing changed.
+    #[must_use = "the old handle is dead if the value changed"]
+    pub fn replace_if_changed<F>(
+        &mut self,
+        h: Handle<T, G>,
+        value: T,
+        changed: F,
+    ) -> Result<Handle<T, G>, HandleError<G>>
+    where
+        F: Fn(&T, &T) -> bool,
+    {
+        let old = self
+            .checked_slot_mut(h)?
+            .val
+            .as_mut()
+            .ok_or(HandleError::Vacant)?;
+        if changed(old, &value) {
+            self.replace(h, value)
+        } else {
+            *old = value;
+            Ok(h)
+        }
+    }
+
+    /// Get a reference to the value of the slot.
+    pub fn get(&self, h: Handle<T, G>) -> Option<&T> {
+        self.slot(h)?.val.as_ref()
+    }
+
+    /// Get a mutable reference to the value of the slot.
+    pub fn get_mut(&mut self, h: Handle<T, G>) -> Option<&mut T> {
+        self.slot_mut(h)?.val.as_mut()
+    }
+
+    /// Whether `h` came from this arena (or was built by hand and so is unbound).
+    /// An arena built without id tracking accepts every handle.
+    fn issued_here(&self, h: Handle<T, G>) -> bool {
+        self.id == UNBOUND_ARENA || h.issued_by(self.id)
+    }
+
+    /// The slot `h` points at, if it was issued by this arena and its generation still matches.
+    fn slot(&self, h: Handle<T, G>) -> Option<&Slot<T, G>> {
+        if !self.issued_here(h) {
+            return None;
+        }
+        let slot = self.slots.get(h.idx)?;
+        (slot.generation == h.generation).then_some(slot)
+    }
+
+    /// Like `get`, but explains why a handle does not resolve. Meant for debugging; `get`
+    /// stays the fast path.
+    pub fn validate(&self, h: Handle<T, G>) -> Result<&T, HandleError<G>> {
+        self.checked_slot(h)?
+            .val
+            .as_ref()
+            .ok_or(HandleError::Vacant)
+    }
+
+    /// `slot`, but with the reason it failed.
+    fn checked_slot(&self, h: Handle<T, G>) -> Result<&Slot<T, G>, HandleError<G>> {
+        if !self.issued_here(h) {
+            return Err(HandleError::ForeignArena);
+        }
+        let slot = self.slots.get(h.idx).ok_or(HandleError::OutOfBounds)?;
+        if slot.generation != h.generation {
+            return Err(HandleError::GenerationMismatch {
+                current: slot.generation,
+                handle: h.generation,
+            });
+        }
+        Ok(slot)
+    }
+
+    /// `slot_mut`, but with the reason it failed.
+    fn checked_slot_mut(&mut self, h: Handle<T, G>) -> Result<&mut Slot<T, G>, HandleError<G>> {
+        self.checked_slot(h)?;
+        Ok(&mut self.slots[h.idx])
+    }
+
+    /// The current generation of slot `idx`, for comparing against a handle that failed.
+    pub fn generation_of(&self, idx: usize) -> Option<G> {
+        self.slots.get(idx).map(|slot| slot.generation)
+    }
+
+    fn slot_mut(&mut self, h: Handle<T, G>) -> Option<&mut Slot<T, G>> {
+        if !self.issued_here(h) {
+            return None;
+        }
+        let slot = self.slots.get_mut(h.idx)?;
+        (slot.generation == h.generation).then_some(slot)
+    }
+
+    /// Get a reference to the value without bounds or generation checks, like `slice::get_unchecked`.
+    ///
+    /// # Safety
+    /// `h` must be live: validate it (e.g. with `contains`) and run no invalidating operation in between.
+    pub unsafe fn get_unchecked(&self, h: Handle<T, G>) -> &T {
+        // SAFETY: the caller guarantees `h.idx` is in bounds and the slot is occupied
+        unsafe {
+            self.slots
+                .get_unchecked(h.idx)
+                .val
+                .as_ref()
+                .unwrap_unchecked()
+        }
+    }
+
+    /// Get a mutable reference to the value without bounds or generation checks.
+    ///
+    /// # Safety
+    /// Same contract as `get_unchecked`: `h` must be live.
+    pub unsafe fn get_unchecked_mut(&mut self, h: Handle<T, G>) -> &mut T {
+        // SAFETY: the caller guarantees `h.idx` is in bounds and the slot is occupied
+        unsafe {
+            self.slots
+                .get_unchecked_mut(h.idx)
+                .val
+                .as_mut()
+                .unwrap_unchecked()
+        }
+    }
+
+    /// Get mutable references to two distinct live slots at once (e.g., parent and child).
+    pub fn get_disjoint_mut(
+        &mut self,
+        a: Handle<T, G>,
+        b: Handle<T, G>,
+    ) -> Option<(&mut T, &mut T)> {
+        let [a, b] = self.get_many_mut([a, b])?;
+        Some((a, b))
+    }
+
+    /// Resolves `N` handles at once, e.g. a triangle's three vertices. Each position is
+    /// resolved on its own, so duplicates are fine and a stale handle only yields `None` there.
+    pub fn get_many<const N: usize>(&self, handles: [Handle<T, G>; N]) -> [Option<&T>; N] {
+        handles.map(|h| self.get(h))
+    }
+
+    /// Mutable references to `N` distinct live elements at once. `None` if any handle is
+    /// stale, foreign or vacant, or if two handles share an index.
+    pub fn get_many_mut<const N: usize>(
+        &mut self,
+        handles: [Handle<T, G>; N],
+    ) -> Option<[&mut T; N]> {
+        if !handles.iter().all(|&h| self.issued_here(h)) {
+            return None;
+        }
+        // Fails on a repeated index or an out-of-bounds index
+        let slots = self.slots.get_disjoint_mut(handles.map(|h| h.idx)).ok()?;
+        let all_live = slots
+            .iter()
+            .zip(&handles)
+            .all(|(slot, h)| slot.generation == h.generation && slot.val.is_some());
+        if !all_live {
+            return None;
+        }
+        Some(slots.map(|slot| slot.val.as_mut().expect("checked above")))
+    }
+
+    /// Inserts `make()` and borrows it mutably together with the existing `neighbor`, e.g. to
+    /// wire up `prev`/`next` links in one scope. The insert happens first, so no earlier
+    /// borrow is held across it. `None` (and nothing inserted) if `neighbor` is stale.
+    pub fn get_mut_pair_or_insert<F>(
+        &mut self,
+        neighbor: Handle<T, G>,
+        make: F,
+    ) -> Option<(Handle<T, G>, &mut T, &mut T)>
+    where
+        F: FnOnce() -> T,
+    {
+        if !self.contains(neighbor) {
+            return None;
+        }
+        let h = self.insert_with(make);
+        let (new, neighbor) = self.get_disjoint_mut(h, neighbor)?;
+        Some((h, new, neighbor))
+    }
+
+    /// Tree-editing spelling of `get_disjoint_mut`, e.g. for reparenting a node.
+    pub fn get_pair_mut(
+        &mut self,
+        parent: Handle<T, G>,
+        child: Handle<T, G>,
+    ) -> Option<(&mut T, &mut T)> {
+        self.get_disjoint_mut(parent, child)
+    }
+
+    /// Resolves a list of handles (e.g. a node's children) to `Elem`s, skipping stale ones.
+    pub fn resolve_all<'a>(
+        &'a self,
+        handles: &'a [Handle<T, G>],
+    ) -> impl Iterator<Item = Elem<'a, T, G>> {
+        handles.iter().filter_map(|&h| Elem::new(self, h))
+    }
+
+    /// Strict `resolve_all`: `None` if any handle is stale.
+    pub fn try_resolve_all<'a>(&'a self, handles: &[Handle<T, G>]) -> Option<Vec<Elem<'a, T, G>>> {
+        handles.iter().map(|&h| Elem::new(self, h)).collect()
+    }
+
+    /// Checks whether the handle still points at a live element, without borrowing it.
+    pub fn contains(&self, h: Handle<T, G>) -> bool {
+        self.slot(h).is_some_and(|slot| slot.val.is_some())
+    }
+
+    /// Runs `f` on the arena and reports, next to its result, whether `h` is still valid
+    /// afterwards. A handle that was already stale going in counts as invalidated.
+    pub fn with_guard<R, F>(&mut self, h: Handle<T, G>, f: F) -> (R, bool)
+    where
+        F: FnOnce(&mut Self) -> R,
+    {
+        let valid_before = self.contains(h);
+        let result = f(self);
+        (result, valid_before && self.contains(h))
+    }
+
+    /// `contains` for each handle, in order.
+    pub fn is_valid_batch(&self, handles: &[Handle<T, G>]) -> Vec<bool> {
+        handles.iter().map(|&h| self.contains(h)).collect()
+    }
+
+    /// Drops the stale handles from a caller's list in place, e.g. to prune a side index.
+    pub fn retain_valid(&self, handles: &mut Vec<Handle<T, G>>) {
+        handles.retain(|&h| self.contains(h));
+    }
+
+    /// Deletes the item: bumps generation and frees the slot.
+    pub fn remove(&mut self, h: Handle<T, G>) -> Option<T> {
+        let old = self.take(h)?;
+        self.check_auto_compact();
+        Some(old)
+    }
+
+    /// `remove` without the auto-compaction check, for batches that must keep the rest of
+    /// their handles valid until they are done.
+    fn take(&mut self, h: Handle<T, G>) -> Option<T> {
+        let old = self.slot_mut(h)?.val.take()?;
+        self.release(h.idx);
+        Some(old)
+    }
+
+    /// Runs `compact` (and the hook) if auto-compaction is on and its ratio is exceeded.
+    fn check_auto_compact(&mut self) {
+        if let Some(ratio) = self.auto_compact
+            && self.free.len() as f64 > ratio * self.len() as f64
+        {
+            let remap = self.compact();
+            if let Some(CompactHook(hook)) = &mut self.on_compact {
+                hook(&remap);
+            }
+        }
+    }
+
+    /// Opt-in automatic compaction: once `remove` leaves more than `ratio` free slots per
+    /// live element, it runs `compact`. Batch removals (`remove_many`, the `retain` family,
+    /// `drain_filter`) check once, when the whole batch is done. Pass `f64::INFINITY` to
+    /// turn it back off.
+    ///
+    /// **This remaps handles behind the caller's back**: any removal may invalidate every
+    /// other handle. Only enable it together with `set_on_compact`, or when nothing keeps
+    /// handles across removals.
+    pub fn set_auto_compact(&mut self, ratio: f64) {
+        self.auto_compact = Some(ratio);
+    }
+
+    /// Registers the callback that receives the old-to-new handle map after each
+    /// auto-compaction, so dependent systems can fix up their stored handles.
+    pub fn set_on_compact<F>(&mut self, f: F)
+    where
+        F: FnMut(&Remap<T, G>) + Send + Sync + 'static,
+    {
+        self.on_compact = Some(CompactHook(Box::new(f)));
+    }
+
+    /// Removes the element and fills its slot with the last live element, like
+    /// `Vec::swap_remove`, so live elements stay packed without a full `compact`.
+    /// The moved element changes index and therefore handle: the returned
+    /// `(old_handle, new_handle)` pair must be applied to any stored references.
+    pub fn swap_remove_compact(&mut self, h: Handle<T, G>) -> Option<(T, Option<Moved<T, G>>)> {
+        let removed = self.slot_mut(h)?.val.take()?;
+        let last = self.slots.iter().rposition(|slot| slot.val.is_some());
+        let Some(last) = last.filter(|&last| last > h.idx) else {
+            self.release(h.idx);
+            return Some((removed, None));
+        };
+        let moved = self.slots[last].val.take()?;
+        let old = Handle::issued(self.id, last, self.slots[last].generation);
+        self.release(last);
+        let slot = &mut self.slots[h.idx];
+        let new = match slot.generation.bump() {
+            Some(generation) => {
+                // The hole is refilled right away, so it never goes on the free list
+                slot.generation = generation;
+                slot.val = Some(moved);
+                Handle::issued(self.id, h.idx, generation)
+            }
+            None => {
+                // generation exhausted: retire the hole and put the value in a free slot
+                self.release(h.idx);
+                self.insert_slot(|| moved)
+            }
+        };
+        self.reorder(old, new);
+        Some((removed, Some((old, new))))
+    }
+
+    /// Removes every handle that is still valid and returns how many were removed.
+    /// Stale handles (including duplicates within the batch) are skipped.
+    pub fn remove_many<I>(&mut self, handles: I) -> usize
+    where
+        I: IntoIterator<Item = Handle<T, G>>,
+    {
+        let removed = handles
+            .into_iter()
+            .filter(|&h| self.take(h).is_some())
+            .count();
+        self.check_auto_compact();
+        removed
+    }
+
+    /// Bulk mutation (e.g., clear or reallocate): invalidate *all* contents.
+    /// Returns how many live elements were dropped. Slots and their capacity are kept,
+    /// so refilling reuses them without reallocating; already-free slots are left alone.
+    pub fn clear(&mut self) -> usize {
+        let mut cleared = 0;
+        for i in 0..self.slots.len() {
+            if self.slots[i].val.is_some() {
+                self.release(i);
+                cleared += 1;
+            }
+        }
+        cleared
+    }
+
+    /// Full fresh start: drops every value and truncates all slots, so the arena behaves
+    /// like a brand-new one and `Handle::new(idx, G::first())` is meaningful again.
+    /// Allocated capacity is kept.
+    ///
+    /// **Unlike `clear`, this gives up generational safety.** Generations restart from the
+    /// first one, so a handle from before the reset can coincidentally resolve to whatever
+    /// is inserted at its index afterwards. Only reset when no old handles are kept around.
+    pub fn reset(&mut self) {
+        self.slots.clear();
+        self.free.clear();
+        self.occupied.clear();
+        self.retired = 0;
+        self.reserved.clear();
+        self.floor = None;
+        if let Some(order) = &mut self.order {
+            order.clear();
+        }
+    }
+
+    /// Takes every live element by value, invalidating each slot as it is yielded.
+    /// Dropping the iterator early still invalidates the rest.
+    pub fn drain(&mut self) -> Drain<'_, T, G> {
+        Drain { vec: self, next: 0 }
+    }
+
+    /// Takes out the elements `pred` accepts, e.g. to move dead entities into a pool.
+    /// Each match is removed (gen bump) as it is yielded, together with its old handle;
+    /// everything else stays valid. Dropping the iterator early leaves the unvisited
+    /// elements alone.
+    pub fn drain_filter<F>(&mut self, pred: F) -> DrainFilter<'_, T, F, G>
+    where
+        F: FnMut(&T) -> bool,
+    {
+        DrainFilter {
+            vec: self,
+            pred,
+            next: 0,
+        }
+    }
+
+    /// Checks `h` once and returns an entry to act on, in the style of `HashMap::entry`.
+    pub fn entry(&mut self, h: Handle<T, G>) -> Entry<'_, T, G> {
+        if self.contains(h) {
+            Entry::Occupied(OccupiedEntry {
+                vec: self,
+                handle: h,
+            })
+        } else {
+            Entry::Vacant(VacantEntry {
+                vec: self,
+                handle: h,
+            })
+        }
+    }
+
+    /// A cursor over the live elements in slot order, positioned on the first one.
+    /// Unlike `iter_mut`, it can remove the element it is on and keep going.
+    pub fn cursor(&mut self) -> Cursor<'_, T, G> {
+        let idx = next_occupied(&self.occupied, 0).unwrap_or(self.slots.len());
+        Cursor { vec: self, idx }
+    }
+
+    /// `swap_values` under its original name, except that two handles to the same slot are
+    /// a no-op rather than an error.
+    #[must_use = "a failed swap leaves both values where they were"]
+    pub fn swap(&mut self, a: Handle<T, G>, b: Handle<T, G>) -> Result<(), HandleError<G>> {
+        match self.swap_values(a, b) {
+            Err(HandleError::SameSlot) => Ok(()),
+            result => result,
+        }
+    }
+
+    /// Swap-without-borrowing-T: contents remain valid (no gen bump).
+    /// Only the values move; each handle keeps its index and now sees the other's value.
+    /// Both handles must be valid and point at distinct slots: `SameSlot` otherwise, e.g.
+    /// for an issued handle and its `Handle::new` rebuild. On failure, the error describes
+    /// `a` if it is invalid, then `b`.
+    #[must_use = "a failed swap leaves both values where they were"]
+    pub fn swap_values(&mut self, a: Handle<T, G>, b: Handle<T, G>) -> Result<(), HandleError<G>> {
+        self.validate(a)?;
+        self.validate(b)?;
+        if a.idx == b.idx {
+            return Err(HandleError::SameSlot);
+        }
+        let [sa, sb] = self
+            .slots
+            .get_disjoint_mut([a.idx, b.idx])
+            .expect("distinct indices are disjoint");
+        mem::swap(&mut sa.val, &mut sb.val);
+        Ok(())
+    }
+
+    /// "Type change" helper for Variant-like containers.
+    /// An empty slot is reported as `Vacant`, whether it is free or a pending
+    /// `reserve_handles` slot (those are filled with `fill`), so `f` always gets `Some`.
+    #[must_use = "a failed map_invalidate does not call `f`"]
+    pub fn map_invalidate<F>(&mut self, h: Handle<T, G>, f: F) -> Result<(), HandleError<G>>
+    where
+        F: FnOnce(Option<T>) -> Option<T>,
+    {
+        if self.checked_slot(h)?.val.is_none() {
+            return Err(HandleError::Vacant);
+        }
+        let slot = &mut self.slots[h.idx];
+        let Some(val) = f(mem::take(&mut slot.val)) else {
+            // Nothing to keep: free the slot just like `remove` would
+            self.release(h.idx);
+            return Ok(());
+        };
+        // Changing contents' identity => bump gen
+        match slot.generation.bump() {
+            Some(generation) => {
+                slot.generation = generation;
+                slot.val = Some(val);
+                self.set_occupied(h.idx, true);
+                self.reorder(h, Handle::issued(self.id, h.idx, generation));
+            }
+            None => {
+                // generation exhausted: retire the slot and move the value elsewhere
+                self.release(h.idx);
+                let new = self.insert_slot(|| val);
+                self.reorder(h, new);
+            }
+        }
+        Ok(())
+    }
+
+    /// Starts recording insertion order for `ordered`; existing elements are taken in slot order.
+    ///
+    /// Costs one `(idx, generation)` pair per insertion on top of the slots. Removals only leave a stale entry
+    /// behind, which is dropped once stale entries outnumber live elements, so the list holds
+    /// at most about twice `len()` handles.
+    pub fn track_order(&mut self) {
+        if self.order.is_none() {
+            self.order = Some(self.handles().map(|h| (h.idx, h.generation)).collect());
+        }
+    }
+
+    /// Live elements in insertion order. `replace` keeps an element's position, even when it
+    /// moves to another slot. Without `track_order` this falls back to slot order, like `iter`.
+    pub fn ordered(&self) -> impl Iterator<Item = (Handle<T, G>, &T)> {
+        let tracked = self.order.as_deref().unwrap_or_default();
+        let untracked = if self.order.is_none() { usize::MAX } else { 0 };
+        tracked
+            .iter()
+            .map(|&(idx, generation)| Handle::issued(self.id, idx, generation))
+            .filter_map(|h| self.get(h).map(|val| (h, val)))
+            .chain(self.iter().take(untracked))
+    }
+
+    /// Moves `old`'s insertion-order entry over to the handle that replaced it.
+    fn reorder(&mut self, old: Handle<T, G>, new: Handle<T, G>) {
+        if let Some(order) = &mut self.order
+            && let Some(entry) = order
+                .iter_mut()
+                .rev()
+                .find(|e| **e == (old.idx, old.generation))
+        {
+            *entry = (new.idx, new.generation);
+        }
+    }
+
+    /// Drops stale insertion-order entries once they outnumber the live elements.
+    fn prune_order(&mut self) {
+        let live = self.len();
+        if let Some(order) = &self.order
+            && order.len() > 2 * live + 16
+        {
+            let mut order = self.order.take().unwrap_or_default();
+            order.retain(|&(idx, generation)| {
+                self.contains(Handle::issued(self.id, idx, generation))
+            });
+            self.order = Some(order);
+        }
+    }
+
+    /// Empties a slot: bumps its generation and frees it, or retires it for good
+    /// when the generation is exhausted.
+    fn release(&mut self, idx: usize) {
+        // A slot on the free list is never marked occupied, so this catches a double free
+        // without scanning `free`.
+        debug_assert!(
+            self.occupied[idx / 64] & (1 << (idx % 64)) != 0,
+            "slot {} released twice",
+            idx
+        );
+        let slot = &mut self.slots[idx];
+        slot.val = None;
+        match slot.generation.bump() {
+            Some(generation) => {
+                slot.generation = generation;
+                self.push_free(idx);
+            }
+            None => self.retired += 1,
+        }
+        self.set_occupied(idx, false);
+    }
+
+    /// Puts an empty slot on the free list, where the `FreePolicy` wants it.
+    fn push_free(&mut self, idx: usize) {
+        self.free.push(idx);
+    }
+
+    /// Packs the given elements into the lowest usable slots, in the order given, for cache
+    /// locality. Whatever lived there is swapped out to the vacated slots. Moved elements
+    /// change index and so handle: the returned map covers every element that moved,
+    /// hot or displaced, and must be applied to stored handles.
+    ///
+    /// Stale handles are ignored. Retired and reserved slots are never used as targets,
+    /// and an element whose slot generation is exhausted stays where it is.
+    pub fn move_to_front(&mut self, handles: &[Handle<T, G>]) -> Remap<T, G> {
+        let mut remap = Remap::new();
+        // Current handle of every moved element -> the handle the caller knows it by
+        let mut origin = Remap::new();
+        let mut target = 0;
+        for &h in handles {
+            let h = remap.get(&h).copied().unwrap_or(h);
+            if !self.contains(h) || h.idx < target {
+                continue; // stale, or already placed (e.g. a duplicate)
+            }
+            while target < h.idx && self.slots[target].val.is_none() && !self.free.contains(target)
+            {
+                target += 1;
+            }
+            if target < h.idx {
+                let Some(moved) = self.swap_slots(h.idx, target) else {
+                    continue;
+                };
+                for (old, new) in moved {
+                    let known = origin.remove(&old).unwrap_or(old);
+                    remap.insert(known, new);
+                    origin.insert(new, known);
+                    self.reorder(old, new);
+                }
+            }
+            target += 1;
+        }
+        remap
+    }
+
+    /// Moves the live element at `from` into the lower slot `to`, which is live or free;
+    /// a live element at `to` goes to `from`. Both get fresh generations. Returns the
+    /// moves, or `None` (changing nothing) if a generation is exhausted.
+    fn swap_slots(&mut self, from: usize, to: usize) -> Option<Vec<Moved<T, G>>> {
+        let from_generation = self.slots[from].generation.bump()?;
+        let to_generation = if self.slots[to].val.is_some() {
+            self.slots[to].generation.bump()?
+        } else {
+            // A free slot's generation has not been handed out yet
+            self.slots[to].generation
+        };
+        let old_from = Handle::issued(self.id, from, self.slots[from].generation);
+        let old_to = Handle::issued(self.id, to, self.slots[to].generation);
+        let [a, b] = self.slots.get_disjoint_mut([from, to]).ok()?;
+        mem::swap(&mut a.val, &mut b.val);
+        a.generation = from_generation;
+        b.generation = to_generation;
+        let mut moved = alloc::vec![(old_from, Handle::issued(self.id, to, to_generation))];
+        if self.slots[from].val.is_some() {
+            moved.push((old_to, Handle::issued(self.id, from, from_generation)));
+        } else {
+            self.free.remove(to);
+            self.push_free(from);
+            self.set_occupied(to, true);
+            self.set_occupied(from, false);
+        }
+        Some(moved)
+    }
+
+    /// Keeps the occupancy bitmap in step with a slot gaining or losing its value.
+    fn set_occupied(&mut self, idx: usize, live: bool) {
+        let (word, bit) = (idx / 64, 1u64 << (idx % 64));
+        if word >= self.occupied.len() {
+            self.occupied.resize(word + 1, 0);
+        }
+        if live {
+            self.occupied[word] |= bit;
+        } else {
+            self.occupied[word] &= !bit;
+        }
+    }
+
+    /// Compaction: moves live elements to the front and drops the freed slots.
+    /// Every live element gets a fresh handle; the returned map takes each old handle to its new one.
+    ///
+    /// **Any handle not present in the returned map is now invalid**, and must not be kept.
+    /// None of them resolves again: every index keeps counting generations past its old
+    /// ones, even once trimmed and reused, and an index whose generations are used up stays
+    /// retired. Such an index can't be packed over or trimmed, so it may be left behind as a
+    /// gap (or keep freed slots before it alive).
+    pub fn compact(&mut self) -> HandleMap<Handle<T, G>, Handle<T, G>> {
+        let old_generations: Vec<G> = self.slots.iter().map(|slot| slot.generation).collect();
+        let floor = self.fresh_generation();
+        let mut remap = HandleMap::new();
+        let old_slots = mem::take(&mut self.slots);
+        let reserved = mem::take(&mut self.reserved);
+        self.free.clear();
+        self.retired = 0;
+        for (idx, slot) in old_slots.into_iter().enumerate() {
+            // Reserved slots move along with the live ones, so their handles get remapped too
+            if slot.val.is_none() && !reserved.contains(&idx) {
+                continue;
+            }
+            // Bump past whatever used to live at the new index, so old handles to it fail;
+            // an index with no generation left is kept as a retired gap
+            let (new_idx, generation) = loop {
+                let new_idx = self.slots.len();
+                let Some(&old) = old_generations.get(new_idx) else {
+                    break (new_idx, floor);
+                };
+                match old.bump() {
+                    Some(generation) => break (new_idx, generation),
+                    None => {
+                        self.slots.push(Slot {
+                            generation: old,
+                            val: None,
+                        });
+                        self.retired += 1;
+                    }
+                }
+            };
+            if slot.val.is_none() {
+                self.reserved.push(new_idx);
+            }
+            self.slots.push(Slot {
+                generation,
+                val: slot.val,
+            });
+            remap.insert(
+                Handle::issued(self.id, idx, slot.generation),
+                Handle::issued(self.id, new_idx, generation),
+            );
+        }
+        // Past the packed elements, slots up to the last retired one have to stay, as free
+        // or retired slots; the rest are trimmed and raise the floor like in `shrink_to_fit`
+        let end = self.slots.len();
+        let keep = old_generations
+            .iter()
+            .rposition(|generation| generation.bump().is_none())
+            .map_or(end, |last| end.max(last + 1));
+        for (idx, &old) in old_generations.iter().enumerate().skip(end) {
+            match old.bump() {
+                Some(generation) if idx >= keep => {
+                    self.floor = Some(self.fresh_generation().max(generation));
+                }
+                Some(generation) => {
+                    self.slots.push(Slot {
+                        generation,
+                        val: None,
+                    });
+                    self.push_free(idx);
+                }
+                None => {
+                    self.slots.push(Slot {
+                        generation: old,
+                        val: None,
+                    });
+                    self.retired += 1;
+                }
+            }
+        }
+        self.occupied.clear();
+        for idx in 0..self.slots.len() {
+            self.set_occupied(idx, self.slots[idx].val.is_some());
+        }
+        if let Some(order) = &mut self.order {
+            *order = order
+                .iter()
+                .filter_map(|&(idx, generation)| {
+                    remap.get(&Handle::issued(self.id, idx, generation))
+                })
+                .map(|h| (h.idx, h.generation))
+                .collect();
+        }
+        remap
+    }
+
+    /// Captures every slot's generation (values are not touched), for structural undo.
+    pub fn snapshot_generations(&self) -> Vec<G> {
+        self.slots.iter().map(|slot| slot.generation).collect()
+    }
+
+    /// Restores generations captured by `snapshot_generations`, re-validating handles that
+    /// were live at snapshot time. Slots added since the snapshot keep their generation.
+    ///
+    /// Sharp edge: values are not restored. If a slot's value changed in between, an old
+    /// handle will now resolve to the new contents, so restore the values separately.
+    pub fn restore_generations(&mut self, snap: &[G]) {
+        for (slot, &generation) in self.slots.iter_mut().zip(snap) {
+            slot.generation = generation;
+        }
+    }
+
+    /// Builds a mirror arena with the exact same layout (indices, generations, free list),
+    /// each live value mapped through `f`. A handle valid here is valid there after `cast`.
+    pub fn map_values<U, F>(&self, mut f: F) -> DynVec<U, G>
+    where
+        F: FnMut(&T) -> U,
+    {
+        DynVec {
+            slots: self
+                .slots
+                .iter()
+                .map(|slot| Slot {
+                    generation: slot.generation,
+                    val: slot.val.as_ref().map(&mut f),
+                })
+                .collect(),
+            free: self.free.clone(),
+            occupied: self.occupied.clone(),
+            retired: self.retired,
+            reserved: self.reserved.clone(),
+            id: self.id,
+            order: self.order.clone(),
+            policy: self.policy,
+            floor: self.floor,
+            auto_compact: None,
+            on_compact: None,
+        }
+    }
+
+    /// Iterate over live elements, yielding a valid handle alongside each value.
+    /// Freed slots are skipped.
+    pub fn iter(&self) -> impl Iterator<Item = (Handle<T, G>, &T)> {
+        self.iter_at(0)
+    }
+
+    /// Like `iter`, but starts at `start`'s slot, e.g. to resume work spread over several
+    /// ticks from the last handle processed. Only the index matters: a stale `start` still
+    /// resumes from its position, with the next live element.
+    pub fn iter_from(&self, start: Handle<T, G>) -> impl Iterator<Item = (Handle<T, G>, &T)> {
+        self.iter_at(start.idx)
+    }
+
+    fn iter_at(&self, start: usize) -> impl Iterator<Item = (Handle<T, G>, &T)> {
+        let arena = self.id;
+        let mut slots = self.slots.get(start..).unwrap_or_default().iter();
+        let mut cursor = start;
+        core::iter::from_fn(move || {
+            let idx = next_occupied(&self.occupied, cursor)?;
+            let slot = slots.nth(idx - cursor)?;
+            cursor = idx + 1;
+            debug_assert!(slot.val.is_some(), "occupancy bitmap out of sync");
+            let val = slot.val.as_ref()?;
+            Some((Handle::issued(arena, idx, slot.generation), val))
+        })
+    }
+
+    /// Mutably iterate over live elements: contents change in place, so no gen bump.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (Handle<T, G>, &mut T)> {
+        let arena = self.id;
+        let occupied = &self.occupied;
+        let mut slots = self.slots.iter_mut();
+        let mut cursor = 0;
+        core::iter::from_fn(move || {
+            let idx = next_occupied(occupied, cursor)?;
+            let slot = slots.nth(idx - cursor)?;
+            cursor = idx + 1;
+            debug_assert!(slot.val.is_some(), "occupancy bitmap out of sync");
+            let generation = slot.generation;
+            let val = slot.val.as_mut()?;
+            Some((Handle::issued(arena, idx, generation), val))
+        })
+    }
+
+    /// Like `iter_mut`, but `f` also gets a shared view of the arena, e.g. so a node can read
+    /// its parent while updating itself. The element being visited is taken out of its slot
+    /// for the call, so the view does not contain it: looking up its own handle through
+    /// the view gives `None`. No generations change. If `f` panics, the element is put back
+    /// before the panic propagates.
+    pub fn for_each_with_rest<F>(&mut self, mut f: F)
+    where
+        F: FnMut(Handle<T, G>, &mut T, &DynVec<T, G>),
+    {
+        let mut cursor = 0;
+        while let Some(idx) = next_occupied(&self.occupied, cursor) {
+            cursor = idx + 1;
+            let slot = &mut self.slots[idx];
+            let h = Handle::issued(self.id, idx, slot.generation);
+            let Some(val) = slot.val.take() else {
+                continue;
+            };
+            self.set_occupied(idx, false);
+            let mut guard = PutBack {
+                vec: &mut *self,
+                idx,
+                val: Some(val),
+            };
+            if let Some(val) = guard.val.as_mut() {
+                f(h, val, &*guard.vec);
+            }
+        }
+    }
+
+    /// Iterate over live values only.
+    pub fn values(&self) -> impl Iterator<Item = &T> {
+        self.iter().map(|(_, val)| val)
+    }
+
+    /// Mutably iterate over live values only (no gen bump).
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.iter_mut().map(|(_, val)| val)
+    }
+
+    /// Iterate over the handles of live elements only.
+    pub fn handles(&self) -> impl Iterator<Item = Handle<T, G>> {
+        self.iter().map(|(h, _)| h)
+    }
+
+    /// Handles of the live elements, sorted by comparing their values with `compare`, e.g.
+    /// for deterministic debug dumps. Nothing moves, so every handle stays valid. The sort
+    /// is stable, so equal values come out in slot order.
+    pub fn sorted_handles_by<F>(&self, mut compare: F) -> Vec<Handle<T, G>>
+    where
+        F: FnMut(&T, &T) -> Ordering,
+    {
+        let mut live: Vec<_> = self.iter().collect();
+        live.sort_by(|(_, a), (_, b)| compare(a, b));
+        live.into_iter().map(|(h, _)| h).collect()
+    }
+
+    /// Keeps only the elements the predicate accepts; dropped slots are removed (gen bump).
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(Handle<T, G>, &T) -> bool,
+    {
+        self.retain_mut(|h, val| f(h, val));
+    }
+
+    /// Like `retain`, but hands out `&mut T` for in-place fixups before the keep/drop decision.
+    pub fn retain_mut<F>(&mut self, mut f: F)
+    where
+        F: FnMut(Handle<T, G>, &mut T) -> bool,
+    {
+        for idx in 0..self.slots.len() {
+            let slot = &mut self.slots[idx];
+            let generation = slot.generation;
+            let Some(val) = slot.val.as_mut() else {
+                continue;
+            };
+            if !f(Handle::issued(self.id, idx, generation), val) {
+                self.release(idx);
+            }
+        }
+        self.check_auto_compact();
+    }
+
+    /// Like `retain`, but hands each removed element to `on_remove` together with its
+    /// handle, e.g. to purge it from side tables or clean up the value. The slot is freed
+    /// before the callback runs, so the handle is already stale by then.
+    pub fn retain_notify<F, N>(&mut self, mut keep: F, mut on_remove: N)
+    where
+        F: FnMut(Handle<T, G>, &T) -> bool,
+        N: FnMut(Handle<T, G>, T),
+    {
+        for idx in 0..self.slots.len() {
+            let slot = &mut self.slots[idx];
+            let h = Handle::issued(self.id, idx, slot.generation);
+            let Some(val) = slot.val.as_ref() else {
+                continue;
+            };
+            if !keep(h, val) {
+                let val = slot.val.take();
+                self.release(idx);
+                if let Some(val) = val {
+                    on_remove(h, val);
+                }
+            }
+        }
+        self.check_auto_compact();
+    }
+
+    /// Get the number of live elements (freed, retired and reserved slots are not counted)
+    pub fn len(&self) -> usize {
+        self.slots.len() - self.free.len() - self.retired - self.reserved.len()
+    }
+
+    /// True when there are no live elements, even if freed slots remain.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Get the number of slots, including freed ones (for debugging/testing purposes)
+    pub fn slot_count(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// The highest generation of any slot (`G::first()` for an empty arena). Walks every slot.
+    pub fn max_generation(&self) -> G {
+        self.slots
+            .iter()
+            .map(|slot| slot.generation)
+            .max()
+            .unwrap_or(G::first())
+    }
+
+    /// How close the busiest slot is to retirement, from 0.0 to 1.0 (see `Generation::spent`).
+    /// Worth watching in long-running processes, since slots that reach 1.0 are retired.
+    pub fn generation_pressure(&self) -> f64 {
+        self.max_generation().spent()
+    }
+
+    /// Number of freed slots waiting to be reused.
+    pub fn free_len(&self) -> usize {
+        self.free.len()
+    }
+
+    /// Releases spare capacity of the free list, e.g. after a burst of removals was reused.
+    pub fn shrink_free_list(&mut self) {
+        self.free.shrink_to_fit();
+    }
+
+    /// Rough number of bytes the arena holds: the struct itself plus the allocated capacity
+    /// of its internal vectors. Heap memory owned by the values is not counted.
+    pub fn memory_footprint(&self) -> usize {
+        let order = self.order.as_ref().map_or(0, Vec::capacity);
+        mem::size_of::<Self>()
+            + self.slots.capacity() * mem::size_of::<Slot<T, G>>()
+            + (self.free.capacity() + self.reserved.capacity()) * mem::size_of::<usize>()
+            + self.occupied.capacity() * mem::size_of::<u64>()
+            + order * mem::size_of::<(usize, G)>()
+    }
+
+    /// A snapshot of the arena's size counters, for capacity planning.
+    pub fn stats(&self) -> ArenaStats {
+        ArenaStats {
+            live: self.len(),
+            free: self.free.len(),
+            slots: self.slots.len(),
+            capacity: self.slots.capacity(),
+            footprint: self.memory_footprint(),
+        }
+    }
+}
+
+/// Size counters returned by `DynVec::stats`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ArenaStats {
+    /// Live elements, as `len` reports them.
+    pub live: usize,
+    /// Freed slots waiting to be reused.
+    pub free: usize,
+    /// All slots, including freed, retired and reserved ones.
+    pub slots: usize,
+    /// Slots the backing storage has room for.
+    pub capacity: usize,
+    /// Bytes, as `memory_footprint` reports them.
+    pub footprint: usize,
+}
+
+/// Parallel iteration over live elements. Each slot is visited by exactly one task,
+/// so handing out `&mut T` in parallel is as safe as `iter_mut`.
+#[cfg(feature = "rayon")]
+impl<T, G: Generation + Send + Sync> DynVec<T, G> {
+    pub fn par_iter(&self) -> impl rayon::iter::ParallelIterator<Item = (Handle<T, G>, &T)>
+    where
+        T: Sync,
+    {
+        use rayon::prelude::*;
+        let arena = self.id;
+        self.slots
+            .par_iter()
+            .enumerate()
+            .filter_map(move |(idx, slot)| {
+                let generation = slot.generation;
+                slot.val
+                    .as_ref()
+                    .map(|val| (Handle::issued(arena, idx, generation), val))
+            })
+    }
+
+    pub fn par_iter_mut(
+        &mut self,
+    ) -> impl rayon::iter::ParallelIterator<Item = (Handle<T, G>, &mut T)>
+    where
+        T: Send,
+    {
+        use rayon::prelude::*;
+        let arena = self.id;
+        self.slots
+            .par_iter_mut()
+            .enumerate()
+            .filter_map(move |(idx, slot)| {
+                let generation = slot.generation;
+                slot.val
+                    .as_mut()
+                    .map(|val| (Handle::issued(arena, idx, generation), val))
+            })
+    }
+
+    /// `retain` with the predicate evaluated in parallel. Only the read-only keep/drop
+    /// decisions run on the pool; the dropped slots are then freed on this thread, in index
+    /// order, so the free list ends up exactly as after `retain`.
+    pub fn par_retain<F>(&mut self, pred: F)
+    where
+        T: Sync,
+        F: Fn(Handle<T, G>, &T) -> bool + Sync,
+    {
+        use rayon::prelude::*;
+        let dropped: Vec<usize> = self
+            .par_iter()
+            .filter(|&(h, val)| !pred(h, val))
+            .map(|(h, _)| h.idx)
+            .collect();
+        for idx in dropped {
+            self.slots[idx].val = None;
+            self.release(idx);
+        }
+        self.check_auto_compact();
+    }
+}
+
+/// Strict equality: the same live values at the same indices with the same generations,
+/// so a handle that resolves in one arena resolves to an equal value in the other.
+/// Freed slots and arena ids are not compared. See `content_eq` for order-insensitive equality.
+impl<T: PartialEq, G: Generation> PartialEq for DynVec<T, G> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len()
+            && self.iter().zip(other.iter()).all(|((a, va), (b, vb))| {
+                a.idx == b.idx && a.generation == b.generation && va == vb
+            })
+    }
+}
+
+impl<T: PartialEq, G: Generation> DynVec<T, G> {
+    /// Loose equality: both arenas hold the same multiset of live values, wherever they sit.
+    /// This is O(n^2), since `T` only needs `PartialEq`.
+    pub fn content_eq<H: Generation>(&self, other: &DynVec<T, H>) -> bool {
+        if self.len() != other.len() {
+            return false;
+        }
+        let mut matched = alloc::vec![false; other.slots.len()];
+        self.values().all(|val| {
+            let found = other
+                .slots
+                .iter()
+                .enumerate()
+                .position(|(idx, slot)| !matched[idx] && slot.val.as_ref() == Some(val));
+            found.map(|idx| matched[idx] = true).is_some()
+        })
+    }
+}
+
+/// Panicking lookup for handles known to be valid; use `get` for the fallible path.
+impl<T, G: Generation> Index<Handle<T, G>> for DynVec<T, G> {
+    type Output = T;
+    fn index(&self, h: Handle<T, G>) -> &Self::Output {
+        self.get(h).expect("invalid or stale handle")
+    }
+}
+
+impl<T, G: Generation> IndexMut<Handle<T, G>> for DynVec<T, G> {
+    fn index_mut(&mut self, h: Handle<T, G>) -> &mut Self::Output {
+        self.get_mut(h).expect("invalid or stale handle")
+    }
+}
+
+/// Builds an arena from an iterator, discarding the handles.
+impl<T, G: Generation> FromIterator<T> for DynVec<T, G> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut vec = Self::default();
+        vec.extend(iter);
+        vec
+    }
+}
+
+/// Bulk insertion; recycled slots are reused before new ones are pushed.
+impl<T, G: Generation> Extend<T> for DynVec<T, G> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        let iter = iter.into_iter();
+        // Only the overflow past the free list needs fresh slots
+        self.reserve(iter.size_hint().0.saturating_sub(self.free.len()));
+        for value in iter {
+            self.insert(value);
+        }
+    }
+}
+
+/// Owning iterator over the live elements of a `DynVec`.
+#[derive(Debug)]
+pub struct IntoIter<T, G = NonZeroU32> {
+    slots: core::iter::Enumerate<alloc::vec::IntoIter<Slot<T, G>>>,
+    arena: u64,
+}
+
+impl<T, G: Generation> Iterator for IntoIter<T, G> {
+    type Item = (Handle<T, G>, T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Freed slots are skipped; the handle carries the slot's final generation.
+        let arena = self.arena;
+        self.slots.by_ref().find_map(|(idx, slot)| {
+            let generation = slot.generation;
+            slot.val
+                .map(|val| (Handle::issued(arena, idx, generation), val))
+        })
+    }
+}
+
+/// Consume the arena, yielding each live element by value.
+impl<T, G: Generation> IntoIterator for DynVec<T, G> {
+    type Item = (Handle<T, G>, T);
+    type IntoIter = IntoIter<T, G>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter {
+            slots: self.slots.into_iter().enumerate(),
+            arena: self.id,
+        }
+    }
+}
+
+/// Draining iterator returned by `DynVec::drain`.
+#[derive(Debug)]
+pub struct Drain<'a, T, G: Generation = NonZeroU32> {
+    vec: &'a mut DynVec<T, G>,
+    next: usize,
+}
+
+impl<T, G: Generation> Iterator for Drain<'_, T, G> {
+    type Item = (Handle<T, G>, T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(slot) = self.vec.slots.get_mut(self.next) {
+            let idx = self.next;
+            self.next += 1;
+            if let Some(val) = slot.val.take() {
+                let generation = slot.generation;
+                self.vec.release(idx);
+                return Some((Handle::issued(self.vec.id, idx, generation), val));
+            }
+        }
+        None
+    }
+}
+
+/// Finish invalidating any slots the caller did not consume.
+impl<T, G: Generation> Drop for Drain<'_, T, G> {
+    fn drop(&mut self) {
+        self.for_each(drop);
+    }
+}
+
+/// Iterator returned by `DynVec::drain_filter`.
+#[derive(Debug)]
+pub struct DrainFilter<'a, T, F, G: Generation = NonZeroU32> {
+    vec: &'a mut DynVec<T, G>,
+    pred: F,
+    next: usize,
+}
+
+impl<T, F, G: Generation> Iterator for DrainFilter<'_, T, F, G>
+where
+    F: FnMut(&T) -> bool,
+{
+    type Item = (Handle<T, G>, T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(idx) = next_occupied(&self.vec.occupied, self.next) {
+            self.next = idx + 1;
+            let slot = &mut self.vec.slots[idx];
+            if !slot.val.as_ref().is_some_and(|val| (self.pred)(val)) {
+                continue;
+            }
+            let generation = slot.generation;
+            let val = slot.val.take()?;
+            self.vec.release(idx);
+            return Some((Handle::issued(self.vec.id, idx, generation), val));
+        }
+        None
+    }
+}
+
+/// Auto-compaction waits until the caller is done with the yielded handles.
+impl<T, F, G: Generation> Drop for DrainFilter<'_, T, F, G> {
+    fn drop(&mut self) {
+        self.vec.check_auto_compact();
+    }
+}
+
+/// A value `for_each_with_rest` took out of its slot; dropping the guard puts it back, also
+/// when `f` panics.
+struct PutBack<'a, T, G: Generation> {
+    vec: &'a mut DynVec<T, G>,
+    idx: usize,
+    val: Option<T>,
+}
+
+impl<T, G: Generation> Drop for PutBack<'_, T, G> {
+    fn drop(&mut self) {
+        if let Some(val) = self.val.take() {
+            self.vec.slots[self.idx].val = Some(val);
+            self.vec.set_occupied(self.idx, true);
+        }
+    }
+}
+
+/// Cursor returned by `DynVec::cursor`. It sits on a live element until it runs off the end,
+/// after which every accessor returns `None`.
+#[derive(Debug)]
+pub struct Cursor<'a, T, G: Generation = NonZeroU32> {
+    vec: &'a mut DynVec<T, G>,
+    idx: usize,
+}
+
+impl<T, G: Generation> Cursor<'_, T, G> {
+    /// The element under the cursor and its handle.
+    pub fn current(&self) -> Option<(Handle<T, G>, &T)> {
+        let slot = self.vec.slots.get(self.idx)?;
+        let val = slot.val.as_ref()?;
+        Some((Handle::issued(self.vec.id, self.idx, slot.generation), val))
+    }
+
+    /// Mutable access to the element under the cursor; the handle stays valid.
+    pub fn current_mut(&mut self) -> Option<(Handle<T, G>, &mut T)> {
+        let slot = self.vec.slots.get_mut(self.idx)?;
+        let val = slot.val.as_mut()?;
+        Some((Handle::issued(self.vec.id, self.idx, slot.generation), val))
+    }
+
+    /// Removes the element under the cursor (bumping its generation, like `remove`) and
+    /// moves on to the next live element.
+    pub fn remove_current(&mut self) -> Option<T> {
+        let val = self.vec.slots.get_mut(self.idx)?.val.take()?;
+        self.vec.release(self.idx);
+        self.advance();
+        Some(val)
+    }
+
+    /// Moves to the next live element, skipping freed slots.
+    pub fn advance(&mut self) {
+        let len = self.vec.slots.len();
+        self.idx = next_occupied(&self.vec.occupied, self.idx.saturating_add(1)).unwrap_or(len);
+    }
+}
+
+/// Entry returned by `DynVec::entry`.
+#[derive(Debug)]
+pub enum Entry<'a, T, G: Generation = NonZeroU32> {
+    Occupied(OccupiedEntry<'a, T, G>),
+    Vacant(VacantEntry<'a, T, G>),
+}
+
+/// A handle that was valid when the entry was taken.
+#[derive(Debug)]
+pub struct OccupiedEntry<'a, T, G: Generation = NonZeroU32> {
+    vec: &'a mut DynVec<T, G>,
+    handle: Handle<T, G>,
+}
+
+impl<'a, T, G: Generation> OccupiedEntry<'a, T, G> {
+    pub fn handle(&self) -> Handle<T, G> {
+        self.handle
+    }
+
+    pub fn get(&self) -> &T {
+        &self.vec[self.handle]
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.vec[self.handle]
+    }
+
+    /// Turns the entry into a reference that lives as long as the arena borrow.
+    pub fn into_mut(self) -> &'a mut T {
+        &mut self.vec[self.handle]
+    }
+
+    /// Removes the element, like `DynVec::remove`.
+    pub fn remove(self) -> T {
+        self.vec
+            .remove(self.handle)
+            .expect("entry handle was checked on creation")
+    }
+
+    /// Swaps in a new value with a new identity, like `DynVec::replace`; returns its handle.
+    pub fn replace(self, value: T) -> Handle<T, G> {
+        self.vec
+            .replace(self.handle, value)
+            .expect("entry handle was checked on creation")
+    }
+}
+
+/// A handle that did not resolve: removed, replaced, foreign, or never issued.
+#[derive(Debug)]
+pub struct VacantEntry<'a, T, G: Generation = NonZeroU32> {
+    vec: &'a mut DynVec<T, G>,
+    handle: Handle<T, G>,
+}
+
+impl<T, G: Generation> VacantEntry<'_, T, G> {
+    pub fn handle(&self) -> Handle<T, G> {
+        self.handle
+    }
+
+    /// Inserts into the handle's old slot, if that slot is the one `insert` would reuse next.
+    /// The new element gets a fresh handle (same index, newer generation); otherwise
+    /// `value` is handed back.
+    pub fn insert_here(self, value: T) -> Result<Handle<T, G>, T> {
+        if !self.vec.issued_here(self.handle) || self.vec.free.peek() != Some(self.handle.idx) {
+            return Err(value);
+        }
+        Ok(self.vec.insert(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pack_round_trip() {
+        let mut vec = DynVec::new();
+        let old = vec.insert("a");
+        vec.remove(old);
+        let h = vec.insert("b");
+        let unpacked = Handle::unpack(h.pack()).unwrap();
+        assert_eq!(unpacked, h);
+        assert_eq!(vec.get(unpacked), Some(&"b"));
+        assert_ne!(Handle::unpack(old.pack()), Some(h));
+        // Zero generation bits are never produced by `pack`
+        assert_eq!(Handle::<()>::unpack(7), None);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        #[derive(serde::Serialize, serde::Deserialize)]
+        struct Node {
+            name: String,
+            next: Option<Handle<Node>>,
+        }
+
+        let mut vec = DynVec::new();
+        let tail = vec.insert(Node {
+            name: "tail".into(),
+            next: None,
+        });
+        // Give the freed slot a later generation, so generations have to survive too
+        let gone = vec.insert(Node {
+            name: "gone".into(),
+            next: None,
+        });
+        vec.remove(gone);
+        let head = vec.insert(Node {
+            name: "head".into(),
+            next: Some(tail),
+        });
+
+        let json = serde_json::to_string(&(&vec, head)).unwrap();
+        let (loaded, loaded_head): (DynVec<Node>, Handle<Node>) =
+            serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(loaded_head, head);
+        assert_eq!(loaded[loaded_head].name, "head");
+        // Handles stored inside the elements come back unbound and still resolve
+        let next = loaded[loaded_head].next.unwrap();
+        assert_eq!(loaded[next].name, "tail");
+        assert_eq!(head.idx, gone.idx);
+        assert!(!loaded.contains(Handle::new(gone.idx, gone.generation)));
+        // The copy is a new arena, so handles the original issued are foreign to it
+        assert!(!loaded.contains(head));
+    }
+
+    #[test]
+    fn exhausted_index_never_revalidates() {
+        let mut vec: DynVec<u32, NonZeroU16> = DynVec::default();
+        // Churn index 0 through every generation it has
+        let first = vec.insert(0);
+        vec.remove(first);
+        let mut last = first;
+        let live = loop {
+            let h = vec.insert(0);
+            if h.idx != 0 {
+                break h;
+            }
+            vec.remove(h);
+            last = h;
+        };
+        assert_eq!(last.generation, NonZeroU16::MAX);
+        assert_eq!((vec.slot_count(), vec.free_len()), (2, 0));
+
+        // Compaction can't pack over the retired index, and later inserts never reuse it
+        let remap = vec.compact();
+        let live = remap[&live];
+        assert_eq!(vec[live], 0);
+        let more = vec.insert_many(0..10);
+        assert!(more.iter().chain([&live]).all(|h| h.idx != 0));
+        assert!(!vec.contains(first));
+        assert!(!vec.contains(last));
+    }
+
+    #[test]
+    fn swap_keeps_handles_valid() {
+        let mut vec = DynVec::new();
+        let a = vec.insert("a");
+        let b = vec.insert("b");
+        vec.swap(a, b).unwrap();
+        assert_eq!((vec.get(a), vec.get(b)), (Some(&"b"), Some(&"a")));
+        // Swapping a slot with itself is a no-op
+        vec.swap(a, Handle::new(a.idx, a.generation)).unwrap();
+        assert_eq!(vec.get(a), Some(&"b"));
+        vec.remove(b);
+        assert!(vec.swap(a, b).is_err());
+        assert_eq!(vec.get(a), Some(&"b"));
+    }
+
+    #[test]
+    fn double_clear() {
+        let mut vec = DynVec::with_capacity(3);
+        let handles = vec.insert_many([1, 2, 3]);
+        assert_eq!(vec.clear(), 3);
+        assert_eq!(vec.clear(), 0);
+        assert!(vec.is_empty());
+        assert!(handles.iter().all(|&h| !vec.contains(h)));
+        // The second clear must not free the slots twice
+        assert_eq!(vec.free_len(), 3);
+        let refilled = vec.insert_many([4, 5, 6]);
+        assert_eq!(vec.slot_count(), 3);
+        assert!(vec.capacity() >= 3);
+        assert!(handles.iter().all(|h| !refilled.contains(h)));
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn par_sum_matches_sequential() {
+        use rayon::prelude::*;
+        let mut vec: DynVec<u64> = DynVec::from_vec((0..1000).collect());
+        vec.retain(|_, &x| x % 3 != 0);
+        let sequential: u64 = vec.values().sum();
+        let parallel: u64 = vec.par_iter().map(|(_, &x)| x).sum();
+        assert_eq!(parallel, sequential);
+    }
+
+    #[test]
+    fn reparent() {
+        struct Node {
+            parent: Option<Handle<Node>>,
+            children: Vec<Handle<Node>>,
+        }
+
+        let mut vec = DynVec::new();
+        let mut node = |parent| {
+            vec.insert(Node {
+                parent,
+                children: Vec::new(),
+            })
+        };
+        let root = node(None);
+        let a = node(Some(root));
+        let b = node(Some(root));
+        vec[root].children = vec![a, b];
+
+        // Move `b` from under `root` to under `a`
+        let (parent, child) = vec.get_pair_mut(root, b).unwrap();
+        parent.children.retain(|&h| h != b);
+        child.parent = Some(a);
+        let (parent, _) = vec.get_pair_mut(a, b).unwrap();
+        parent.children.push(b);
+
+        assert_eq!(vec[root].children, [a]);
+        assert_eq!(vec[a].children, [b]);
+        assert_eq!(vec[b].parent, Some(a));
+        // A node can't be its own parent
+        assert!(vec.get_pair_mut(a, a).is_none());
+    }
+
+    #[test]
+    fn get_many_mut_rejects_repeats() {
+        let mut vec = DynVec::new();
+        let a = vec.insert(1);
+        let b = vec.insert(2);
+        assert!(vec.get_many_mut([a, b, a]).is_none());
+        assert!(
+            vec.get_many_mut([a, Handle::new(a.idx, a.generation)])
+                .is_none()
+        );
+        let [x, y] = vec.get_many_mut([b, a]).unwrap();
+        mem::swap(x, y);
+        assert_eq!((vec[a], vec[b]), (2, 1));
+    }
+
+    #[test]
+    fn swap_values_rejects_same_slot() {
+        let mut vec = DynVec::new();
+        let a = vec.insert("a");
+        let b = vec.insert("b");
+        assert_eq!(vec.swap_values(a, a), Err(HandleError::SameSlot));
+        assert_eq!(
+            vec.swap_values(a, Handle::new(a.idx, a.generation)),
+            Err(HandleError::SameSlot)
+        );
+        vec.swap_values(a, b).unwrap();
+        assert_eq!((vec[a], vec[b]), ("b", "a"));
+    }
+
+    #[test]
+    fn map_invalidate_to_none_frees_the_slot() {
+        let mut vec = DynVec::new();
+        let a = vec.insert(1);
+        let b = vec.insert(2);
+        vec.map_invalidate(a, |_| None).unwrap();
+        assert_eq!(vec.len(), 1);
+        assert_eq!(vec.iter().count(), 1);
+        assert_eq!(vec.free_len(), 1);
+        assert!(!vec.contains(a));
+        // The freed index is reused instead of leaking
+        let c = vec.insert(3);
+        assert_eq!(c.idx, a.idx);
+        assert_eq!(vec.slot_count(), 2);
+        assert_eq!((vec.len(), vec[b], vec[c]), (2, 2, 3));
+    }
+
+    #[test]
+    fn map_invalidate_rejects_empty_slots() {
+        let mut vec = DynVec::new();
+        let [reserved] = vec.reserve_handles(1).try_into().unwrap();
+        let a = vec.insert(1);
+        vec.remove(a);
+        let rebuilt = Handle::new(a.idx, vec.generation_of(a.idx).unwrap());
+        for h in [rebuilt, reserved] {
+            assert_eq!(vec.map_invalidate(h, |_| Some(2)), Err(HandleError::Vacant));
+        }
+        assert_eq!((vec.len(), vec.iter().count()), (0, 0));
+        // The free slot is still free, and the reservation still pending
+        assert_eq!(vec.free_len(), 1);
+        vec.fill(reserved, 3).unwrap();
+        assert_eq!(vec.insert(4).idx, a.idx);
+        assert_eq!(vec.len(), 2);
+    }
+
+    #[test]
+    fn reserve_then_fill() {
+        let mut vec = DynVec::new();
+        let a = vec.insert("a");
+        vec.remove(a);
+        let handles = vec.reserve_handles(3);
+        // The freed slot is reserved first, then new slots are appended
+        assert_eq!(handles.iter().map(|h| h.idx).collect::<Vec<_>>(), [0, 1, 2]);
+        assert!(handles.iter().all(|&h| vec.get(h).is_none()));
+        assert_eq!((vec.len(), vec.free_len()), (0, 0));
+        // `insert` never takes a reserved slot
+        let b = vec.insert("b");
+        assert_eq!(b.idx, 3);
+
+        assert_eq!(vec.fill(handles[1], "x"), Ok(()));
+        assert_eq!(vec.fill(handles[1], "again"), Err("again"));
+        assert_eq!(vec.fill(a, "stale"), Err("stale"));
+        assert_eq!(vec.fill(b, "live"), Err("live"));
+        assert_eq!(vec[handles[1]], "x");
+        assert_eq!(vec.len(), 2);
+
+        // Pending reservations move with `compact`, so their handles are remapped too
+        let remap = vec.compact();
+        let pending = remap[&handles[2]];
+        assert_eq!(vec.fill(pending, "y"), Ok(()));
+        assert_eq!(vec.fill(handles[0], "z"), Err("z"));
+        assert_eq!(vec.fill(remap[&handles[0]], "z"), Ok(()));
+        assert_eq!(vec.len(), 4);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_rebuilds_occupancy() {
+        // A stale `occupied` bitmap is ignored, and the first saved format still loads
+        let json = r#"{"slots":[{"generation":1,"val":5},{"generation":2,"val":null}],
+            "free":[1],"occupied":[]}"#;
+        let vec: DynVec<u32> = serde_json::from_str(json).unwrap();
+        assert_eq!((vec.len(), vec.iter().count(), vec.free_len()), (1, 1, 1));
+        assert_eq!(vec.values().collect::<Vec<_>>(), [&5]);
+
+        // Free and reserved lists must name distinct empty slots
+        for bad in [r#"[3]"#, r#"[0]"#, r#"[1, 1]"#] {
+            let json = format!(
+                r#"{{"slots":[{{"generation":1,"val":5}},{{"generation":2,"val":null}}],"free":{bad}}}"#
+            );
+            assert!(serde_json::from_str::<DynVec<u32>>(&json).is_err(), "{bad}");
+        }
+
+        // An empty slot on neither list is a retired one, and is never handed out again
+        let json = r#"{"slots":[{"generation":1,"val":null},{"generation":1,"val":7}],"free":[]}"#;
+        let mut vec: DynVec<u32> = serde_json::from_str(json).unwrap();
+        assert_eq!(vec.len(), 1);
+        assert_eq!(vec.insert(8).idx, 2);
+    }
+
+    #[test]
+    fn lowest_index_policy() {
+        let mut vec = DynVec::with_free_policy(FreePolicy::LowestIndex);
+        let handles = vec.insert_many(0..8);
+        for i in [5, 1, 6, 3] {
+            vec.remove(handles[i]);
+        }
+        #[cfg(feature = "serde")]
+        {
+            let json = serde_json::to_string(&vec).unwrap();
+            let mut loaded: DynVec<i32> = serde_json::from_str(&json).unwrap();
+            let reused: Vec<_> = (0..4).map(|_| loaded.insert(0).idx).collect();
+            assert_eq!(reused, [1, 3, 5, 6]);
+        }
+        let reused: Vec<_> = (0..4).map(|_| vec.insert(0).idx).collect();
+        assert_eq!(reused, [1, 3, 5, 6]);
+
+        // A bulk free leaves every index reusable, lowest first
+        vec.clear();
+        vec.remove(handles[0]);
+        let reused: Vec<_> = (0..8).map(|_| vec.insert(0).idx).collect();
+        assert_eq!(reused, [0, 1, 2, 3, 4, 5, 6, 7]);
+        assert_eq!(vec.free_len(), 0);
+    }
+
+    #[test]
+    fn dangling_survives_packing() {
+        let dangling = Handle::<()>::dangling();
+        let unpacked = Handle::<()>::unpack(dangling.pack()).unwrap();
+        assert!(unpacked.is_dangling());
+        assert_eq!(unpacked, dangling);
+        // Flat arrays of packed handles use it as their null
+        let mut vec = DynVec::new();
+        let h = vec.insert(1);
+        let packed = [h.pack(), Handle::<i32>::dangling().pack()];
+        let resolved: Vec<_> = packed
+            .iter()
+            .map(|&bits| vec.get(Handle::unpack(bits).unwrap()))
+            .collect();
+        assert_eq!(resolved, [Some(&1), None]);
+        assert_ne!(packed[0], packed[1]);
+    }
+
+    #[test]
+    fn arena_id_guards_lookups_only() {
+        let mut a = DynVec::new();
+        let mut b = DynVec::new();
+        let ha = a.insert("a");
+        let hb = b.insert("b");
+        assert_eq!(ha, hb);
+        assert_eq!(b.validate(ha), Err(HandleError::ForeignArena));
+        // A rebuilt handle is unbound, so both arenas resolve it
+        let rebuilt = Handle::new(ha.idx, ha.generation);
+        assert_eq!((a.get(rebuilt), b.get(rebuilt)), (Some(&"a"), Some(&"b")));
+        let mut remap = HandleMap::new();
+        remap.insert(ha, "issued");
+        assert_eq!(remap.get(&rebuilt), Some(&"issued"));
+    }
+
+    #[test]
+    fn shrink_to_fit_keeps_trimmed_handles_stale() {
+        let mut vec = DynVec::with_capacity(100);
+        let handles = vec.insert_many(0..10);
+        vec.track_order();
+        for &h in &handles[4..] {
+            vec.remove(h);
+        }
+        vec.shrink_to_fit();
+        assert_eq!(vec.slot_count(), 4);
+        assert!(vec.capacity() < 100);
+        assert_eq!(vec.free_len(), 0);
+
+        // Reused trimmed indices start past every generation they had before
+        let reused = vec.insert_many(10..16);
+        for (old, new) in handles[4..].iter().zip(&reused) {
+            assert_eq!(old.idx, new.idx);
+            assert!(new.generation > old.generation);
+            assert!(!vec.contains(*old));
+        }
+        assert_eq!(vec.ordered().count(), 10);
+    }
+
+    #[test]
+    fn replace_in_place_keeps_the_handle() {
+        let mut vec = DynVec::new();
+        let h = vec.insert("a");
+        assert_eq!(vec.replace_in_place(h, "b"), Some("a"));
+        assert_eq!(vec.get(h), Some(&"b"));
+        vec.remove(h);
+        assert_eq!(vec.replace_in_place(h, "c"), None);
+        assert!(vec.is_empty());
+    }
+
+    #[test]
+    fn remove_many_skips_stale_and_repeated_handles() {
+        let mut vec = DynVec::new();
+        let handles = vec.insert_many(0..5);
+        vec.remove(handles[0]);
+        let removed = vec.remove_many([handles[0], handles[1], handles[3], handles[1]]);
+        assert_eq!(removed, 2);
+        assert_eq!(vec.values().collect::<Vec<_>>(), [&2, &4]);
+        assert_eq!(vec.free_len(), 3);
+    }
+
+    #[test]
+    fn get_or_insert_with_interns() {
+        let mut vec = DynVec::new();
+        let a = vec.get_or_insert_with(|s| *s == "a", || "a");
+        let b = vec.get_or_insert_with(|s| *s == "b", || "b");
+        let again = vec.get_or_insert_with(|s| *s == "a", || unreachable!());
+        assert_eq!(again, a);
+        assert_ne!(a, b);
+        assert_eq!(vec.len(), 2);
+    }
+
+    #[test]
+    fn ordered_follows_insertion() {
+        let mut vec = DynVec::new();
+        let handles = vec.insert_many(["a", "b", "c"]);
+        vec.track_order();
+        vec.remove(handles[0]);
+        // Reuses slot 0, but comes last in insertion order
+        let d = vec.insert("d");
+        assert_eq!(d.idx, 0);
+        // `replace` keeps the element's position
+        let c = vec.replace(handles[2], "C").unwrap();
+        let order: Vec<_> = vec.ordered().map(|(_, &val)| val).collect();
+        assert_eq!(order, ["b", "C", "d"]);
+        assert_eq!(vec.ordered().nth(1).map(|(h, _)| h), Some(c));
+        let by_slot: Vec<_> = vec.values().copied().collect();
+        assert_eq!(by_slot, ["d", "b", "C"]);
+
+        // Stale entries don't pile up under churn
+        for _ in 0..100 {
+            let h = vec.insert("x");
+            vec.remove(h);
+        }
+        assert!(vec.order.as_ref().unwrap().len() <= 2 * vec.len() + 16);
+    }
+
+    #[test]
+    fn from_vec_handles_match_indices() {
+        let mut vec: DynVec<_> = DynVec::from_vec(vec!["a", "b", "c"]);
+        let b = vec.handle_for(1);
+        assert_eq!(vec[b], "b");
+        assert_eq!(vec.len(), 3);
+        vec.remove(b);
+        // A later occupant of the slot doesn't revive the old index handle
+        vec.insert("d");
+        assert!(!vec.contains(vec.handle_for(1)));
+        assert_eq!(vec.get(vec.handle_for(2)), Some(&"c"));
+    }
+
+    #[test]
+    fn cursor_removes_while_walking() {
+        let mut vec = DynVec::new();
+        let handles = vec.insert_many(0..6);
+        vec.remove(handles[1]);
+        let mut cursor = vec.cursor();
+        let mut seen = Vec::new();
+        while let Some((h, &val)) = cursor.current() {
+            seen.push(val);
+            if val % 2 == 0 {
+                assert_eq!(cursor.remove_current(), Some(val));
+                assert!(!cursor.vec.contains(h));
+            } else {
+                *cursor.current_mut().unwrap().1 *= 10;
+                cursor.advance();
+            }
+        }
+        assert_eq!(seen, [0, 2, 3, 4, 5]);
+        assert!(cursor.current_mut().is_none());
+        assert_eq!(vec.values().collect::<Vec<_>>(), [&30, &50]);
+        assert_eq!(vec[handles[3]], 30);
+    }
+
+    #[test]
+    fn replace_if_changed_bumps_only_on_change() {
+        let mut vec = DynVec::new();
+        let h = vec.insert(Ok::<u32, u32>(1));
+        let kind_changed =
+            |old: &Result<u32, u32>, new: &Result<u32, u32>| old.is_ok() != new.is_ok();
+        assert_eq!(vec.replace_if_changed(h, Ok(2), kind_changed), Ok(h));
+        assert_eq!(vec[h], Ok(2));
+        let new = vec.replace_if_changed(h, Err(3), kind_changed).unwrap();
+        assert_ne!(new, h);
+        assert!(!vec.contains(h));
+        assert_eq!(vec[new], Err(3));
+        assert!(vec.replace_if_changed(h, Ok(4), kind_changed).is_err());
+    }
+
+    #[test]
+    fn clone_from_copies_generations_and_free_list() {
+        let mut source = DynVec::new();
+        let handles = source.insert_many([String::from("a"), String::from("b")]);
+        source.remove(handles[0]);
+        let mut target = DynVec::new();
+        target.insert_many((0..8).map(|i| i.to_string()));
+        target.clone_from(&source);
+        assert_eq!(target, source);
+        assert_eq!((target.len(), target.free_len()), (1, 1));
+        assert_eq!(target[handles[1]], "b");
+        assert!(!target.contains(handles[0]));
+        // Both reuse the freed slot at the same generation
+        assert_eq!(target.insert("c".into()), source.insert("c".into()));
+    }
+
+    #[test]
+    fn swap_remove_compact_moves_the_last_element() {
+        let mut vec = DynVec::new();
+        let handles = vec.insert_many(["a", "b", "c", "d"]);
+        let (removed, moved) = vec.swap_remove_compact(handles[1]).unwrap();
+        assert_eq!(removed, "b");
+        let (old, new) = moved.unwrap();
+        assert_eq!(old, handles[3]);
+        assert_eq!(new.idx, 1);
+        assert_eq!(vec[new], "d");
+        assert!(!vec.contains(handles[1]) && !vec.contains(handles[3]));
+        assert_eq!(vec.values().collect::<Vec<_>>(), [&"a", &"d", &"c"]);
+
+        // Removing the last live element moves nothing
+        assert_eq!(vec.swap_remove_compact(handles[2]), Some(("c", None)));
+        assert_eq!(vec.swap_remove_compact(handles[2]), None);
+        assert_eq!(vec.len(), 2);
+    }
+
+    #[test]
+    fn generation_pressure_tracks_the_busiest_slot() {
+        let mut vec: DynVec<u8, NonZeroU16> = DynVec::default();
+        assert_eq!(vec.generation_pressure(), 0.0);
+        let calm = vec.insert(0);
+        // Churn one slot halfway through its generations
+        for _ in 0..u16::MAX / 2 {
+            let h = vec.insert(1);
+            vec.remove(h);
+        }
+        let pressure = vec.generation_pressure();
+        assert!((0.49..0.51).contains(&pressure), "{pressure}");
+        assert_eq!(vec.max_generation().get(), u16::MAX / 2 + 1);
+        assert_eq!(vec[calm], 0);
+    }
+
+    #[test]
+    fn get_mut_or_insert_upserts() {
+        let mut vec = DynVec::new();
+        let (h, count) = vec.get_mut_or_insert(None, || 0);
+        *count += 1;
+        let (again, count) = vec.get_mut_or_insert(Some(h), || 0);
+        *count += 1;
+        assert_eq!((again, vec[h]), (h, 2));
+        vec.remove(h);
+        let (fresh, count) = vec.get_mut_or_insert(Some(h), u32::default);
+        assert_eq!(*count, 0);
+        assert_ne!(fresh, h);
+        assert_eq!(vec.len(), 1);
+    }
+
+    #[test]
+    fn auto_compaction_runs_the_hook_once_per_batch() {
+        use std::sync::{Arc, Mutex};
+        let remaps = Arc::new(Mutex::new(Vec::new()));
+        let mut vec = DynVec::builder().auto_compact_ratio(1.0).build();
+        let seen = Arc::clone(&remaps);
+        vec.set_on_compact(move |remap| seen.lock().unwrap().push(remap.clone()));
+        let handles = vec.insert_many(0..10);
+
+        // 4 free slots for 6 live elements: under the ratio
+        vec.remove_many(handles[..4].iter().copied());
+        assert!(remaps.lock().unwrap().is_empty());
+        // A batch compacts once it is done, never halfway through
+        vec.remove_many(handles[4..8].iter().copied());
+        let remaps = remaps.lock().unwrap();
+        assert_eq!(remaps.len(), 1);
+        let remap = &remaps[0];
+        assert_eq!(remap.len(), 2);
+        assert_eq!((vec.slot_count(), vec.free_len()), (2, 0));
+        assert_eq!((vec[remap[&handles[8]]], vec[remap[&handles[9]]]), (8, 9));
+        assert!(!vec.contains(handles[9]));
+    }
+
+    #[test]
+    fn entry_occupied_and_vacant() {
+        let mut vec = DynVec::new();
+        let a = vec.insert(1);
+        let Entry::Occupied(mut entry) = vec.entry(a) else {
+            panic!("live handle gave a vacant entry");
+        };
+        *entry.get_mut() += 1;
+        assert_eq!(entry.remove(), 2);
+
+        // The freed slot is next in line, so the vacant entry can refill it
+        let Entry::Vacant(entry) = vec.entry(a) else {
+            panic!("stale handle gave an occupied entry");
+        };
+        let b = entry.insert_here(3).unwrap();
+        assert_eq!((b.idx, vec[b]), (a.idx, 3));
+        let c = vec.insert(4);
+        vec.remove(b);
+        vec.remove(c);
+        // Now `c`'s slot is reused first, so `b`'s entry can't insert
+        let Entry::Vacant(entry) = vec.entry(b) else {
+            panic!("stale handle gave an occupied entry");
+        };
+        assert_eq!(entry.insert_here(5), Err(5));
+
+        let d = vec.insert(6);
+        let Entry::Occupied(entry) = vec.entry(d) else {
+            panic!("live handle gave a vacant entry");
+        };
+        let e = entry.replace(7);
+        assert!(!vec.contains(d));
+        assert_eq!(vec[e], 7);
+    }
+
+    #[test]
+    fn sorted_handles_by_is_stable() {
+        let mut vec = DynVec::new();
+        let handles = vec.insert_many([3, 1, 2, 1]);
+        let sorted = vec.sorted_handles_by(|a, b| a.cmp(b));
+        assert_eq!(sorted, [handles[1], handles[3], handles[2], handles[0]]);
+        let values: Vec<_> = sorted.iter().map(|&h| vec[h]).collect();
+        assert_eq!(values, [1, 1, 2, 3]);
+        assert_eq!(vec.values().collect::<Vec<_>>(), [&3, &1, &2, &1]);
+    }
+
+    #[test]
+    fn merge_remaps_cross_references() {
+        struct Link {
+            val: u32,
+            next: Option<Handle<Link>>,
+        }
+
+        let mut vec = DynVec::new();
+        let kept = vec.insert(Link { val: 0, next: None });
+        let freed = vec.insert(Link { val: 1, next: None });
+        vec.remove(freed);
+        let mut other = DynVec::new();
+        let target = other.insert(Link { val: 2, next: None });
+        let pointer = other.insert(Link {
+            val: 3,
+            next: Some(target),
+        });
+        let remap = vec.merge(other);
+        assert_eq!(vec.len(), 3);
+        // The freed slot is reused first
+        assert_eq!(remap[&target].idx, freed.idx);
+        let next = vec[remap[&pointer]].next.unwrap();
+        assert_eq!(vec[remap[&next]].val, 2);
+        assert_eq!(vec[kept].val, 0);
+    }
+
+    #[test]
+    fn is_valid_batch_matches_contains() {
+        let mut vec = DynVec::new();
+        let handles = vec.insert_many(0..4);
+        vec.remove(handles[2]);
+        let mut batch = vec![handles[0], handles[2], Handle::dangling(), handles[3]];
+        assert_eq!(vec.is_valid_batch(&batch), [true, false, false, true]);
+        vec.retain_valid(&mut batch);
+        assert_eq!(batch, [handles[0], handles[3]]);
+    }
+
+    #[test]
+    fn memory_footprint_follows_capacity() {
+        let empty = DynVec::<u64>::new();
+        assert_eq!(empty.memory_footprint(), mem::size_of::<DynVec<u64>>());
+        let mut vec = DynVec::<u64>::with_capacity(64);
+        let reserved = vec.memory_footprint();
+        assert!(
+            reserved >= empty.memory_footprint() + 64 * mem::size_of::<Slot<u64, NonZeroU32>>()
+        );
+        // Filling reserved capacity allocates only the bitmap and free list, not slots
+        let handles = vec.insert_many(0..64);
+        vec.remove_many(handles);
+        let filled = vec.memory_footprint();
+        assert!(filled > reserved);
+        assert!(filled - reserved < 64 * mem::size_of::<Slot<u64, NonZeroU32>>());
+        vec.reset();
+        vec.shrink_to_fit();
+        vec.shrink_free_list();
+        assert!(vec.memory_footprint() < filled);
+    }
+
+    #[test]
+    fn move_to_front_chains_remaps() {
+        let mut vec = DynVec::new();
+        let h = vec.insert_many(["a", "b", "c", "d", "e"]);
+        // `e` displaces `a` to the back, then `a` itself is moved forward again: the map
+        // must take the caller's original handles to the final ones
+        let remap = vec.move_to_front(&[h[4], h[0], h[0]]);
+        let values: Vec<_> = vec.values().copied().collect();
+        assert_eq!(values, ["e", "a", "c", "d", "b"]);
+        assert_eq!(remap.len(), 3);
+        for (i, name) in [(0, "a"), (1, "b"), (4, "e")] {
+            assert_eq!(vec[remap[&h[i]]], name);
+            assert!(!vec.contains(h[i]));
+        }
+        assert_eq!((vec[h[2]], vec[h[3]]), ("c", "d"));
+    }
+
+    #[test]
+    fn builder_configures_the_arena() {
+        let mut vec = DynVec::<u8>::builder()
+            .capacity(16)
+            .free_policy(FreePolicy::LowestIndex)
+            .track_arena_id(false)
+            .build();
+        assert!(vec.capacity() >= 16);
+        let handles = vec.insert_many(0..4);
+        vec.remove(handles[2]);
+        vec.remove(handles[0]);
+        assert_eq!(vec.insert(9).idx, 0);
+
+        // Without arena ids, handles are interchangeable between such arenas
+        let mut other = DynVec::<u8>::builder().track_arena_id(false).build();
+        other.insert_many([7, 8]);
+        assert_eq!(other.get(handles[1]), Some(&8));
+        assert_eq!(other.validate(handles[3]), Err(HandleError::OutOfBounds));
+
+        let default = DynVecBuilder::<u8, NonZeroU16>::default().build();
+        assert_eq!(default.max_generation(), NonZeroU16::MIN);
+    }
+
+    #[test]
+    fn get_mut_pair_or_insert_links_nodes() {
+        struct Node {
+            val: u32,
+            prev: Option<Handle<Node>>,
+            next: Option<Handle<Node>>,
+        }
+
+        let mut vec = DynVec::new();
+        let head = vec.insert(Node {
+            val: 0,
+            prev: None,
+            next: None,
+        });
+        let (tail, new, neighbor) = vec
+            .get_mut_pair_or_insert(head, || Node {
+                val: 1,
+                prev: None,
+                next: None,
+            })
+            .unwrap();
+        new.prev = Some(head);
+        neighbor.next = Some(tail);
+        assert_eq!(vec[vec[head].next.unwrap()].val, 1);
+        assert_eq!(vec[tail].prev, Some(head));
+
+        // A stale neighbor inserts nothing
+        vec.remove(head);
+        let made = vec.get_mut_pair_or_insert(head, || unreachable!());
+        assert!(made.is_none());
+        assert_eq!(vec.len(), 1);
+    }
+
+    #[test]
+    fn find_scans_live_elements_in_slot_order() {
+        let mut vec = DynVec::new();
+        let handles = vec.insert_many([1, 4, 6, 9]);
+        vec.remove(handles[1]);
+        assert_eq!(vec.find(|&x| x % 2 == 0), Some((handles[2], &6)));
+        assert_eq!(vec.find_handle(|&x| x > 1), Some(handles[2]));
+        assert_eq!(vec.find_handle(|&x| x > 10), None);
+        let odd: Vec<_> = vec.find_all(|&x| x % 2 == 1).map(|(h, _)| h).collect();
+        assert_eq!(odd, [handles[0], handles[3]]);
+    }
+
+    #[test]
+    fn replace_with_computes_from_the_old_value() {
+        let mut vec = DynVec::new();
+        let h = vec.insert(String::from("a"));
+        let new = vec.replace_with(h, |old| old + "b").unwrap();
+        assert_eq!((new.idx, vec[new].as_str()), (h.idx, "ab"));
+        assert_eq!(
+            vec.replace_with(h, |old| old),
+            Err(HandleError::GenerationMismatch {
+                current: new.generation,
+                handle: h.generation,
+            })
+        );
+
+        // A panicking `f` leaves the element removed, not half-emptied
+        let caught = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            vec.replace_with(new, |_| panic!("boom"))
+        }));
+        assert!(caught.is_err());
+        assert!(vec.is_empty());
+        assert_eq!(vec.free_len(), 1);
+        assert_eq!(vec.insert(String::new()).idx, h.idx);
+    }
+
+    #[test]
+    fn drain_filter_takes_matches_only() {
+        let mut vec = DynVec::new();
+        let handles = vec.insert_many(0..6);
+        let drained: Vec<_> = vec.drain_filter(|&x| x % 2 == 0).collect();
+        assert_eq!(drained, [(handles[0], 0), (handles[2], 2), (handles[4], 4)]);
+        assert_eq!(vec.len(), 3);
+        assert!(!vec.contains(handles[2]));
+        assert_eq!(vec[handles[3]], 3);
+
+        // Stopping early leaves the unvisited matches in place
+        let first = vec.drain_filter(|_| true).next();
+        assert_eq!(first, Some((handles[1], 1)));
+        assert_eq!(vec.values().collect::<Vec<_>>(), [&3, &5]);
+    }
+
+    #[test]
+    fn for_each_with_rest_sees_the_other_elements() {
+        struct Node {
+            parent: Option<Handle<Node>>,
+            depth: u32,
+        }
+
+        let mut vec = DynVec::new();
+        let root = vec.insert(Node {
+            parent: None,
+            depth: 0,
+        });
+        let child = vec.insert(Node {
+            parent: Some(root),
+            depth: 0,
+        });
+        vec.insert(Node {
+            parent: Some(child),
+            depth: 0,
+        });
+        // Slot order is parent-first here, so one pass settles every depth
+        vec.for_each_with_rest(|h, node, rest| {
+            assert!(rest.get(h).is_none());
+            if let Some(parent) = node.parent {
+                node.depth = rest[parent].depth + 1;
+            }
+        });
+        assert_eq!(vec.values().map(|n| n.depth).collect::<Vec<_>>(), [0, 1, 2]);
+        assert_eq!(vec.len(), 3);
+    }
+
+    #[test]
+    fn for_each_with_rest_puts_back_on_panic() {
+        let mut vec = DynVec::new();
+        let handles = vec.insert_many([1, 2, 3]);
+        let caught = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            vec.for_each_with_rest(|_, val, _| {
+                *val *= 10;
+                if *val == 20 {
+                    panic!("boom");
+                }
+            })
+        }));
+        assert!(caught.is_err());
+        // The element being visited went back, along with its edit, and nothing else moved
+        assert_eq!(vec.values().collect::<Vec<_>>(), [&10, &20, &3]);
+        assert_eq!(vec.iter().count(), vec.len());
+        assert!(handles.iter().all(|&h| vec.contains(h)));
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn par_retain_matches_retain() {
+        let mut sequential: DynVec<u32> = DynVec::from_vec((0..500).collect());
+        sequential.remove_many(
+            (0..500)
+                .step_by(7)
+                .map(|i| sequential.handle_for(i))
+                .collect::<Vec<_>>(),
+        );
+        let mut parallel = sequential.clone();
+        sequential.retain(|h, &x| x % 3 != 0 && h.idx % 5 != 0);
+        parallel.par_retain(|h, &x| x % 3 != 0 && h.idx % 5 != 0);
+        assert_eq!(parallel, sequential);
+        assert_eq!(parallel.free_len(), sequential.free_len());
+        // Same free list order, so both reuse the same slots
+        let reused = |vec: &mut DynVec<u32>| (0..50).map(|i| vec.insert(i)).collect::<Vec<_>>();
+        assert_eq!(reused(&mut parallel), reused(&mut sequential));
+    }
+
+    #[test]
+    fn with_guard_reports_invalidation() {
+        let mut vec = DynVec::new();
+        let a = vec.insert(1);
+        let b = vec.insert(2);
+        let (len, survived) = vec.with_guard(a, |vec| {
+            vec.remove(b);
+            vec.len()
+        });
+        assert_eq!((len, survived), (1, true));
+        let (_, survived) = vec.with_guard(a, |vec| vec.replace(a, 3));
+        assert!(!survived);
+        // Already stale going in
+        let ((), survived) = vec.with_guard(b, |_| ());
+        assert!(!survived);
+    }
+
+    #[test]
+    fn clone_slot_leaves_the_original_alone() {
+        let mut vec = DynVec::new();
+        let a = vec.insert(vec![1, 2]);
+        let copy = vec.clone_slot(a).unwrap();
+        assert_ne!(copy, a);
+        vec[copy].push(3);
+        assert_eq!((vec[a].len(), vec[copy].len()), (2, 3));
+        vec.remove(a);
+        assert_eq!(vec.clone_slot(a), None);
+        assert_eq!(vec.len(), 1);
     }
 }
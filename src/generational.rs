@@ -1,26 +1,30 @@
+use crate::dynvec::{self, DynVec, Generation};
+use core::fmt;
+use core::mem;
+use core::num::NonZeroU32;
+
 /// A handle that stays valid until the variant’s generation changes.
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Handle {
     pub generation: u32,
 }
 
-#[allow(dead_code)]
-#[derive(Debug)]
-pub enum MyVariant {
-    Int(i32),
-    Text(String),
-    Bool(bool),
+/// A payload with a cheap type tag, so callers can branch on the kind of value a
+/// `GenVariant` holds without matching the whole thing.
+pub trait Variant {
+    type Kind: Copy + Eq;
+    fn kind(&self) -> Self::Kind;
 }
 
+/// A single value whose identity changes on `set`, e.g. a variant switching payload types.
 #[derive(Debug)]
-pub struct GenVariant {
-    inner: MyVariant,
+pub struct GenVariant<T> {
+    inner: T,
     generation: u32,
 }
 
-#[allow(dead_code)]
-impl GenVariant {
-    pub fn new(inner: MyVariant) -> Self {
+impl<T> GenVariant<T> {
+    pub fn new(inner: T) -> Self {
         Self {
             inner,
             generation: 0,
@@ -34,14 +38,140 @@ impl GenVariant {
         }
     }
 
-    /// Accessor that returns `Some(&MyVariant)` if still valid.
-    pub fn get(&self, h: Handle) -> Option<&MyVariant> {
-        (h.generation == self.generation).then(|| &self.inner)
+    /// Accessor that returns `Some(&T)` if still valid.
+    pub fn get(&self, h: Handle) -> Option<&T> {
+        (h.generation == self.generation).then_some(&self.inner)
+    }
+
+    /// Edit the current payload in place: no bump, so outstanding handles stay valid.
+    pub fn get_mut(&mut self, h: Handle) -> Option<&mut T> {
+        (h.generation == self.generation).then_some(&mut self.inner)
     }
 
     /// Mutate to a different payload → bump generation, invalidating old handles.
-    pub fn set(&mut self, new_inner: MyVariant) {
-        self.inner = new_inner;
+    pub fn set(&mut self, new_inner: T) {
+        self.replace(new_inner);
+    }
+
+    /// Like `set`, but hands back the old payload and its generation for a later `rollback`.
+    pub fn replace(&mut self, new_inner: T) -> (T, u32) {
+        let old = (mem::replace(&mut self.inner, new_inner), self.generation);
         self.generation = self.generation.wrapping_add(1);
+        old
+    }
+
+    /// Undo a `set`: restores `previous` at `to_generation`, so handles issued before it are
+    /// valid again. Only roll back to a pair obtained from `replace`; any other generation
+    /// may revive handles that should stay dead. Note that the next `set` re-issues the
+    /// undone generation, so handles taken between `replace` and `rollback` must be dropped.
+    pub fn rollback(&mut self, previous: T, to_generation: u32) {
+        self.inner = previous;
+        self.generation = to_generation;
+    }
+}
+
+impl<T: Variant> GenVariant<T> {
+    /// The kind of the current payload.
+    pub fn kind(&self) -> T::Kind {
+        self.inner.kind()
+    }
+
+    /// Whether the current payload is of the given kind.
+    pub fn is(&self, kind: T::Kind) -> bool {
+        self.kind() == kind
+    }
+}
+
+/// A handle into a `DynVec<GenVariant<T>>` that checks both levels: the arena slot
+/// (invalidated by `remove`/`replace`) and the variant inside it (invalidated by `set`).
+pub struct NestedHandle<T, G = NonZeroU32> {
+    pub outer: dynvec::Handle<GenVariant<T>, G>,
+    pub inner: Handle,
+}
+
+// Manual impls so that `NestedHandle<T>` is `Copy` regardless of `T`.
+impl<T, G: Generation> Clone for NestedHandle<T, G> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T, G: Generation> Copy for NestedHandle<T, G> {}
+
+impl<T, G: Generation> PartialEq for NestedHandle<T, G> {
+    fn eq(&self, other: &Self) -> bool {
+        self.outer == other.outer && self.inner == other.inner
+    }
+}
+
+impl<T, G: Generation> Eq for NestedHandle<T, G> {}
+
+impl<T, G: Generation> fmt::Debug for NestedHandle<T, G> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NestedHandle")
+            .field("outer", &self.outer)
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+/// Two-level lookups on an arena of variants; every method fails if either level is stale.
+pub trait NestedVariants<T, G = NonZeroU32> {
+    /// Pins the variant currently stored at `outer`.
+    fn nested_handle(&self, outer: dynvec::Handle<GenVariant<T>, G>) -> Option<NestedHandle<T, G>>;
+    fn get_nested(&self, h: NestedHandle<T, G>) -> Option<&T>;
+    /// Edits the payload in place; neither generation changes.
+    fn get_nested_mut(&mut self, h: NestedHandle<T, G>) -> Option<&mut T>;
+}
+
+impl<T, G: Generation> NestedVariants<T, G> for DynVec<GenVariant<T>, G> {
+    fn nested_handle(&self, outer: dynvec::Handle<GenVariant<T>, G>) -> Option<NestedHandle<T, G>> {
+        let inner = self.get(outer)?.handle();
+        Some(NestedHandle { outer, inner })
+    }
+
+    fn get_nested(&self, h: NestedHandle<T, G>) -> Option<&T> {
+        self.get(h.outer)?.get(h.inner)
+    }
+
+    fn get_nested_mut(&mut self, h: NestedHandle<T, G>) -> Option<&mut T> {
+        self.get_mut(h.outer)?.get_mut(h.inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn get_mut_keeps_handles_valid() {
+        let mut variant = GenVariant::new(1);
+        let h = variant.handle();
+        *variant.get_mut(h).unwrap() += 1;
+        assert_eq!(variant.get(h), Some(&2));
+        assert_eq!(variant.handle(), h);
+        variant.set(3);
+        assert!(variant.get_mut(h).is_none());
+    }
+
+    #[test]
+    fn nested_handles_check_both_levels() {
+        let mut vec = DynVec::new();
+        let outer = vec.insert(GenVariant::new(1));
+        let nested = vec.nested_handle(outer).unwrap();
+        *vec.get_nested_mut(nested).unwrap() += 1;
+        assert_eq!(vec.get_nested(nested), Some(&2));
+
+        // `set` on the variant invalidates the inner level only
+        vec[outer].set(3);
+        assert_eq!(vec.get_nested(nested), None);
+        let nested = vec.nested_handle(outer).unwrap();
+        assert_eq!(vec.get_nested(nested), Some(&3));
+
+        // Replacing the arena slot invalidates the outer level
+        let outer = vec.replace(outer, GenVariant::new(4)).unwrap();
+        assert_eq!(vec.get_nested(nested), None);
+        assert!(vec.get_nested_mut(nested).is_none());
+        assert_eq!(vec.get_nested(vec.nested_handle(outer).unwrap()), Some(&4));
     }
 }
@@ -0,0 +1,13 @@
+// Without the `std` feature the arenas only need `core` and `alloc`, e.g. on embedded targets.
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+pub mod chunked;
+pub mod dense;
+pub mod dynvec;
+pub mod generational;
+pub mod stable;
+#[cfg(feature = "std")]
+pub mod sync;
+pub mod weak;
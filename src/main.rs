@@ -1,15 +1,48 @@
-mod dynvec;
-mod generational;
-mod weak;
-
-use dynvec::{DynVec, Handle};
-use weak::Elem;
+// The arenas live in the library, which builds without `std`; the demo prints through `std`.
+use rabbit_mem::dynvec::{DynVec, Handle, HandleError};
+use rabbit_mem::generational::{GenVariant, NestedVariants, Variant};
+#[cfg(feature = "std")]
+use rabbit_mem::sync;
+use rabbit_mem::weak::Elem;
 
 #[derive(Debug, Clone)]
 struct TreeNode {
     name: String,
-    parent: Option<Handle>,
-    children: Vec<Handle>,
+    parent: Option<Handle<TreeNode>>,
+    children: Vec<Handle<TreeNode>>,
+}
+
+#[allow(dead_code)]
+#[derive(Debug)]
+enum MyVariant {
+    Int(i32),
+    Text(String),
+    Bool(bool),
+    Float(f64),
+    List(Vec<MyVariant>),
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum VariantKind {
+    Int,
+    Text,
+    Bool,
+    Float,
+    List,
+}
+
+impl Variant for MyVariant {
+    type Kind = VariantKind;
+    fn kind(&self) -> VariantKind {
+        match self {
+            MyVariant::Int(_) => VariantKind::Int,
+            MyVariant::Text(_) => VariantKind::Text,
+            MyVariant::Bool(_) => VariantKind::Bool,
+            MyVariant::Float(_) => VariantKind::Float,
+            MyVariant::List(_) => VariantKind::List,
+        }
+    }
 }
 
 fn main() {
@@ -23,6 +56,13 @@ fn main() {
 
     // Example 3: Content mutation invalidating references
     content_mutation_example();
+
+    // Example 4: A single generational value
+    variant_example();
+
+    // Example 5: Handles resolved from several threads
+    #[cfg(feature = "std")]
+    shared_arena_example();
 }
 
 fn tree_example() {
@@ -62,18 +102,19 @@ fn tree_example() {
     println!("Root node: {}", root_elem.name);
 
     // Accessing parent from child nodes
-    for child_handle in &root_elem.children {
-        if let Some(child_elem) = Elem::new(&nodes, *child_handle) {
-            // Get the TreeNode data to access parent field
-            if let Some(child_node) = nodes.get(*child_handle) {
-                if let Some(parent_handle) = child_node.parent {
-                    if let Some(parent) = nodes.get(parent_handle) {
-                        println!("  Child: {} (parent: {})", child_elem.name, parent.name);
-                    }
-                }
-            }
+    for child_elem in nodes.resolve_all(&root_elem.children) {
+        // `Elem::parent` is the arena, so deref explicitly to reach the node's own field
+        if let Some(parent_handle) = (*child_elem).parent
+            && let Some(parent) = nodes.get(parent_handle)
+        {
+            println!("  Child: {} (parent: {})", child_elem.name, parent.name);
         }
     }
+
+    // Look a node up by name, without keeping an index of names to handles
+    if let Some((handle, node)) = nodes.find(|node| node.name == "Child2") {
+        println!("Found {} at index {}", node.name, handle.idx);
+    }
     println!();
 }
 
@@ -104,6 +145,11 @@ fn graph_example() {
         println!("Node A: {}", name);
     }
 
+    // Walk every live node without holding onto the handles
+    for (handle, name) in nodes.iter() {
+        println!("  [{}@{}] {}", handle.idx, handle.generation, name);
+    }
+
     println!();
 }
 
@@ -115,18 +161,17 @@ fn content_mutation_example() {
 
     // Create a node
     let handle = nodes.insert("Original Content".to_string());
-    println!("Created node with content: {}", nodes.get(handle).unwrap());
+    println!("Created node with content: {}", nodes[handle]);
 
     // Mutate the content - this should still work since we don't bump generation
-    *nodes.get_mut(handle).unwrap() = "Modified Content".to_string();
-    println!("Content modified to: {}", nodes.get(handle).unwrap());
+    nodes[handle] = "Modified Content".to_string();
+    println!("Content modified to: {}", nodes[handle]);
 
-    // Create a weak reference after mutation
-    let weak_ref = Elem::new(&nodes, handle).unwrap();
-    println!("Weak reference created: {}", *weak_ref);
-
-    // Drop the weak reference before mutating
-    drop(weak_ref);
+    // Create a weak reference after mutation; it goes out of scope before mutating
+    {
+        let weak_ref = Elem::new(&nodes, handle).unwrap();
+        println!("Weak reference created: {}", weak_ref);
+    }
 
     // Now replace the content (which bumps generation)
     println!("Handle generation before replace: {}", handle.generation);
@@ -135,21 +180,125 @@ fn content_mutation_example() {
         .unwrap();
     println!(
         "Content replaced (generation bumped): {}",
-        nodes.get(new_handle).unwrap()
+        nodes[new_handle]
     );
     println!("New handle generation: {}", new_handle.generation);
 
     // Try to create a weak reference with the old handle - should be invalid
     if let Some(old_weak_ref) = Elem::new(&nodes, handle) {
-        println!("Old weak reference still valid: {}", *old_weak_ref);
+        println!("Old weak reference still valid: {}", old_weak_ref);
     } else {
         println!("Old weak reference is invalid (as expected after generation bump)");
     }
 
     // Create a weak reference with the new handle - should work
     if let Some(new_weak_ref) = Elem::new(&nodes, new_handle) {
-        println!("New weak reference is valid: {}", *new_weak_ref);
+        println!("New weak reference is valid: {}", new_weak_ref);
+    }
+
+    // Replacing through the old handle fails, and the error says why
+    match nodes.replace(handle, "Lost Update".to_string()) {
+        Ok(_) => println!("Old handle replaced the content (unexpected)"),
+        Err(HandleError::GenerationMismatch { current, handle }) => println!(
+            "Replace refused: slot is at generation {}, old handle has {}",
+            current, handle
+        ),
+        Err(err) => println!("Replace refused: {}", err),
+    }
+
+    println!();
+}
+
+fn variant_example() {
+    println!("4. Generational Variant");
+    println!("=======================");
+
+    let mut variant = GenVariant::new(MyVariant::Int(42));
+    let handle = variant.handle();
+    println!("Variant contents: {:?}", variant.get(handle));
+
+    // Switching the payload bumps the generation
+    variant.set(MyVariant::Text("forty-two".to_string()));
+    match variant.get(handle) {
+        Some(inner) => println!("Old handle still valid: {:?}", inner),
+        None => println!("Old handle is invalid (as expected after set)"),
     }
+    println!("New handle sees: {:?}", variant.get(variant.handle()));
+
+    // Editing inside a list goes through `get_mut`, so the handle survives
+    variant.set(MyVariant::List(vec![
+        MyVariant::Int(1),
+        MyVariant::Float(2.5),
+    ]));
+    let handle = variant.handle();
+    if let Some(MyVariant::List(items)) = variant.get_mut(handle) {
+        items[0] = MyVariant::Bool(true);
+    }
+    println!(
+        "Still a {:?}, same handle after editing an item: {:?}",
+        variant.kind(),
+        variant.get(handle)
+    );
+
+    // Variants can live in an arena too; a nested handle checks both generations
+    let mut variants = DynVec::new();
+    let outer = variants.insert(GenVariant::new(MyVariant::Int(1)));
+    let nested = variants.nested_handle(outer).unwrap();
+    println!("Nested handle sees: {:?}", variants.get_nested(nested));
+    variants[outer].set(MyVariant::Bool(false));
+    println!(
+        "After an inner set, nested handle sees: {:?}",
+        variants.get_nested(nested)
+    );
 
     println!();
 }
+
+#[cfg(feature = "std")]
+fn shared_arena_example() {
+    println!("5. Shared Arena Across Threads");
+    println!("==============================");
+
+    let nodes = sync::SyncDynVec::new(DynVec::new());
+    let handles: Vec<_> = (1..=4).map(|i| nodes.insert(i * 10)).collect();
+
+    // Handles are Copy, so every reader gets its own; the arena is only read-locked
+    std::thread::scope(|scope| {
+        for (worker, handle) in handles.iter().enumerate() {
+            let nodes = &nodes;
+            scope.spawn(move || {
+                let value = nodes.with(*handle, |value| *value);
+                println!("  Worker {} read {:?}", worker, value);
+            });
+        }
+    });
+
+    let total: i32 = nodes.read().values().sum();
+    println!("Sum of all nodes: {}", total);
+
+    println!();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn float_and_list_kinds() {
+        let mut variant = GenVariant::new(MyVariant::Float(1.5));
+        assert_eq!(variant.kind(), VariantKind::Float);
+        let h = variant.handle();
+        variant.set(MyVariant::List(vec![
+            MyVariant::Int(1),
+            MyVariant::Bool(true),
+        ]));
+        assert!(variant.is(VariantKind::List));
+        assert!(!variant.is(VariantKind::Float));
+        assert!(variant.get(h).is_none());
+        let MyVariant::List(items) = variant.get(variant.handle()).unwrap() else {
+            panic!("expected a list");
+        };
+        let kinds: Vec<_> = items.iter().map(Variant::kind).collect();
+        assert_eq!(kinds, [VariantKind::Int, VariantKind::Bool]);
+    }
+}
@@ -0,0 +1,151 @@
+use crate::dynvec::{DynVec, Generation, Handle, next_arena_id};
+use alloc::vec::Vec;
+use core::num::NonZeroU32;
+
+/// One entry of the indirection table: the generation handles to it must carry, and where
+/// its element currently lives in the inner arena, as `(idx, generation)`.
+#[derive(Debug)]
+struct Indirection<G> {
+    generation: G,
+    target: Option<(usize, G)>,
+}
+
+/// A `DynVec` whose handles survive `compact`. Handles index an indirection table rather
+/// than the storage, and `compact` only rewrites the table entries, so every lookup pays
+/// one extra indexing step. Use a plain `DynVec` when handles can be remapped instead.
+/// The table itself is never compacted, since its indices are the handles.
+#[derive(Debug)]
+pub struct StableDynVec<T, G = NonZeroU32> {
+    // Each element remembers its table entry, so iteration can rebuild its handle
+    inner: DynVec<(usize, T), G>,
+    table: Vec<Indirection<G>>,
+    free: Vec<usize>,
+    id: u64,
+}
+
+impl<T, G> Default for StableDynVec<T, G> {
+    fn default() -> Self {
+        Self {
+            inner: DynVec::default(),
+            table: Vec::new(),
+            free: Vec::new(),
+            id: next_arena_id(),
+        }
+    }
+}
+
+impl<T> StableDynVec<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<T, G: Generation> StableDynVec<T, G> {
+    /// Inserts a new item, reusing a freed table entry if there is one.
+    pub fn insert(&mut self, value: T) -> Handle<T, G> {
+        let idx = self.free.pop().unwrap_or_else(|| {
+            self.table.push(Indirection {
+                generation: G::first(),
+                target: None,
+            });
+            self.table.len() - 1
+        });
+        let target = self.inner.insert((idx, value));
+        let entry = &mut self.table[idx];
+        entry.target = Some((target.idx, target.generation));
+        Handle::issued(self.id, idx, entry.generation)
+    }
+
+    /// Get a reference to the value, through the indirection table.
+    pub fn get(&self, h: Handle<T, G>) -> Option<&T> {
+        self.inner.get(self.target(h)?).map(|(_, val)| val)
+    }
+
+    /// Get a mutable reference to the value (no gen bump).
+    pub fn get_mut(&mut self, h: Handle<T, G>) -> Option<&mut T> {
+        let target = self.target(h)?;
+        self.inner.get_mut(target).map(|(_, val)| val)
+    }
+
+    /// Checks whether the handle still points at a live element.
+    pub fn contains(&self, h: Handle<T, G>) -> bool {
+        self.target(h).is_some()
+    }
+
+    /// Deletes the item: bumps the table entry's generation and frees it, or retires it for
+    /// good when the generation is exhausted.
+    pub fn remove(&mut self, h: Handle<T, G>) -> Option<T> {
+        let target = self.target(h)?;
+        let entry = &mut self.table[h.idx];
+        entry.target = None;
+        if let Some(generation) = entry.generation.bump() {
+            entry.generation = generation;
+            self.free.push(h.idx);
+        }
+        self.inner.remove(target).map(|(_, val)| val)
+    }
+
+    /// Compacts the storage like `DynVec::compact`, but every handle stays valid: only the
+    /// table entries are rewritten.
+    pub fn compact(&mut self) {
+        self.inner.compact();
+        for (target, &(idx, _)) in self.inner.iter() {
+            self.table[idx].target = Some((target.idx, target.generation));
+        }
+    }
+
+    /// Iterate over live elements in storage order, with their (stable) handles.
+    pub fn iter(&self) -> impl Iterator<Item = (Handle<T, G>, &T)> {
+        self.inner.values().map(|(idx, val)| {
+            let generation = self.table[*idx].generation;
+            (Handle::issued(self.id, *idx, generation), val)
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    /// Where `h`'s element lives in the inner arena, if `h` is still valid.
+    fn target(&self, h: Handle<T, G>) -> Option<Handle<(usize, T), G>> {
+        if !h.issued_by(self.id) {
+            return None;
+        }
+        let entry = self.table.get(h.idx)?;
+        if entry.generation != h.generation {
+            return None;
+        }
+        let (idx, generation) = entry.target?;
+        Some(Handle::new(idx, generation))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn handles_survive_compaction() {
+        let mut vec = StableDynVec::new();
+        let handles: Vec<_> = (0..6).map(|i| vec.insert(i)).collect();
+        for &h in handles.iter().step_by(2) {
+            vec.remove(h);
+        }
+        vec.compact();
+        assert_eq!(vec.len(), 3);
+        for (i, &h) in handles.iter().enumerate() {
+            assert_eq!(vec.get(h), (i % 2 == 1).then_some(&i));
+        }
+        // A freed table entry is reused with a new generation, so the old handle stays dead
+        let new = vec.insert(10);
+        assert_eq!(new.idx, handles[4].idx);
+        assert!(!vec.contains(handles[4]));
+        let mut iterated: Vec<_> = vec.iter().map(|(h, &val)| (h.idx, val)).collect();
+        iterated.sort();
+        assert_eq!(iterated, [(1, 1), (3, 3), (4, 10), (5, 5)]);
+    }
+}
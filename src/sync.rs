@@ -0,0 +1,98 @@
+use crate::dynvec::{DynVec, Generation, Handle};
+use std::num::NonZeroU32;
+use std::sync::{PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// A `DynVec` shared across threads behind a `RwLock`.
+/// `DynVec<T>` is already `Send`/`Sync` whenever `T` is, and handles are plain `Copy` data,
+/// so only the storage needs guarding; many readers can resolve handles in parallel.
+#[derive(Debug)]
+pub struct SyncDynVec<T, G = NonZeroU32> {
+    inner: RwLock<DynVec<T, G>>,
+}
+
+impl<T, G> Default for SyncDynVec<T, G> {
+    fn default() -> Self {
+        Self {
+            inner: RwLock::new(DynVec::default()),
+        }
+    }
+}
+
+impl<T, G: Generation> SyncDynVec<T, G> {
+    pub fn new(vec: DynVec<T, G>) -> Self {
+        Self {
+            inner: RwLock::new(vec),
+        }
+    }
+
+    /// Shared access to the whole arena. A poisoned lock is recovered, since a panicking
+    /// writer cannot leave a slot half-updated in a way that makes handles unsound.
+    pub fn read(&self) -> RwLockReadGuard<'_, DynVec<T, G>> {
+        self.inner.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Exclusive access to the whole arena.
+    pub fn write(&self) -> RwLockWriteGuard<'_, DynVec<T, G>> {
+        self.inner.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Inserts under the write lock.
+    pub fn insert(&self, value: T) -> Handle<T, G> {
+        self.write().insert(value)
+    }
+
+    /// Removes under the write lock.
+    pub fn remove(&self, h: Handle<T, G>) -> Option<T> {
+        self.write().remove(h)
+    }
+
+    /// Resolves `h` under the read lock and hands the element to `f`.
+    pub fn with<R>(&self, h: Handle<T, G>, f: impl FnOnce(&T) -> R) -> Option<R> {
+        self.read().get(h).map(f)
+    }
+
+    /// Resolves `h` under the write lock and hands the element to `f` (no gen bump).
+    pub fn with_mut<R>(&self, h: Handle<T, G>, f: impl FnOnce(&mut T) -> R) -> Option<R> {
+        self.write().get_mut(h).map(f)
+    }
+
+    pub fn into_inner(self) -> DynVec<T, G> {
+        self.inner
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn threads_share_handles() {
+        let vec = SyncDynVec::new(DynVec::new());
+        let handles: Vec<_> = (0..4u64).map(|i| vec.insert(i)).collect();
+        let vec = &vec;
+        std::thread::scope(|s| {
+            for &h in &handles {
+                s.spawn(move || vec.with_mut(h, |val| *val *= 10));
+            }
+        });
+        let sums: Vec<_> = std::thread::scope(|s| {
+            let readers: Vec<_> = (0..4)
+                .map(|_| {
+                    s.spawn(|| {
+                        handles
+                            .iter()
+                            .filter_map(|&h| vec.with(h, |&val| val))
+                            .sum::<u64>()
+                    })
+                })
+                .collect();
+            readers.into_iter().map(|r| r.join().unwrap()).collect()
+        });
+        assert_eq!(sums, [60; 4]);
+        vec.remove(handles[0]);
+        assert_eq!(vec.with(handles[0], |_| ()), None);
+        assert_eq!(vec.read().len(), 3);
+    }
+}
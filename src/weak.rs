@@ -1,18 +1,151 @@
-use std::ops::Deref;
-use crate::dynvec::{DynVec, Handle};
+use crate::dynvec::{DynVec, Generation, Handle};
+use core::fmt;
+use core::hash::{Hash, Hasher};
+use core::marker::PhantomData;
+use core::num::NonZeroU32;
+use core::ops::{Deref, DerefMut};
+use core::ptr;
 
-pub struct Elem<'a, T> {
-    pub parent: &'a DynVec<T>,
-    pub handle: Handle,
+pub struct Elem<'a, T, G = NonZeroU32> {
+    pub parent: &'a DynVec<T, G>,
+    pub handle: Handle<T, G>,
 }
 
-impl<'a, T> Elem<'a, T> {
-    pub fn new(parent: &'a DynVec<T>, handle: Handle) -> Option<Self> {
+impl<'a, T, G: Generation> Elem<'a, T, G> {
+    pub fn new(parent: &'a DynVec<T, G>, handle: Handle<T, G>) -> Option<Self> {
         parent.get(handle).map(|_| Self { parent, handle })
     }
+
+    /// Non-panicking alternative to `Deref`: `None` once the element has been invalidated.
+    pub fn try_deref(&self) -> Option<&'a T> {
+        self.parent.get(self.handle)
+    }
+
+    /// The handle this reference resolves through.
+    pub fn handle(&self) -> Handle<T, G> {
+        self.handle
+    }
+
+    /// Cheap re-check before a `Deref` that would otherwise panic.
+    pub fn is_valid(&self) -> bool {
+        self.parent.contains(self.handle)
+    }
+
+    /// Projects to a part of the element, like `Ref::map`. The element is still resolved
+    /// through the handle on every access, so the projection goes stale along with it.
+    pub fn map<U: ?Sized, F>(self, f: F) -> MappedElem<'a, T, U, F, G>
+    where
+        F: Fn(&T) -> &U,
+    {
+        MappedElem {
+            elem: self,
+            project: f,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<'a, T, G: Generation> Deref for Elem<'a, T, G> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        self.try_deref()
+            .expect("use-after-invalidate: element no longer valid")
+    }
+}
+
+/// Formats the element, or `Elem(<invalidated>)` if the handle no longer resolves.
+impl<'a, T: fmt::Debug, G: Generation> fmt::Debug for Elem<'a, T, G> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.try_deref() {
+            Some(val) => val.fmt(f),
+            None => f.write_str("Elem(<invalidated>)"),
+        }
+    }
+}
+
+impl<'a, T: fmt::Display, G: Generation> fmt::Display for Elem<'a, T, G> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.try_deref() {
+            Some(val) => val.fmt(f),
+            None => f.write_str("Elem(<invalidated>)"),
+        }
+    }
+}
+
+// Manual impls so that `Elem<T>` is `Copy` regardless of `T`. A copy shares the borrow and
+// the handle, so it is exactly as valid as the original; nothing is re-checked.
+impl<'a, T, G: Generation> Clone for Elem<'a, T, G> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, T, G: Generation> Copy for Elem<'a, T, G> {}
+
+/// Identity, not value, equality: the same handle into the same arena. `Elem`s into two
+/// different arenas are never equal, even if their handles coincide.
+impl<'a, T, G: Generation> PartialEq for Elem<'a, T, G> {
+    fn eq(&self, other: &Self) -> bool {
+        ptr::eq(self.parent, other.parent) && self.handle == other.handle
+    }
+}
+
+impl<'a, T, G: Generation> Eq for Elem<'a, T, G> {}
+
+/// Hashes the arena address and handle, matching `PartialEq`.
+impl<'a, T, G: Generation> Hash for Elem<'a, T, G> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        ptr::hash(self.parent, state);
+        self.handle.hash(state);
+    }
+}
+
+/// An `Elem` projected through `Elem::map`, e.g. to a single field of the element.
+pub struct MappedElem<'a, T, U: ?Sized, F, G = NonZeroU32> {
+    elem: Elem<'a, T, G>,
+    project: F,
+    marker: PhantomData<fn() -> &'a U>,
+}
+
+impl<'a, T, U: ?Sized, F, G: Generation> MappedElem<'a, T, U, F, G>
+where
+    F: Fn(&T) -> &U,
+{
+    /// Non-panicking alternative to `Deref`: `None` once the element has been invalidated.
+    pub fn try_deref(&self) -> Option<&'a U> {
+        self.elem.try_deref().map(&self.project)
+    }
+
+    /// The handle of the element this projects from.
+    pub fn handle(&self) -> Handle<T, G> {
+        self.elem.handle
+    }
+}
+
+impl<'a, T, U: ?Sized, F, G: Generation> Deref for MappedElem<'a, T, U, F, G>
+where
+    F: Fn(&T) -> &U,
+{
+    type Target = U;
+    fn deref(&self) -> &Self::Target {
+        (self.project)(&self.elem)
+    }
+}
+
+/// Mutable counterpart to `Elem`: holds the arena exclusively, so nothing can invalidate
+/// the element behind its back.
+pub struct ElemMut<'a, T, G = NonZeroU32> {
+    pub parent: &'a mut DynVec<T, G>,
+    pub handle: Handle<T, G>,
+}
+
+impl<'a, T, G: Generation> ElemMut<'a, T, G> {
+    pub fn new(parent: &'a mut DynVec<T, G>, handle: Handle<T, G>) -> Option<Self> {
+        parent.contains(handle).then_some(Self { parent, handle })
+    }
 }
 
-impl<'a, T> Deref for Elem<'a, T> {
+impl<'a, T, G: Generation> Deref for ElemMut<'a, T, G> {
     type Target = T;
     fn deref(&self) -> &Self::Target {
         self.parent
@@ -20,3 +153,81 @@ impl<'a, T> Deref for Elem<'a, T> {
             .expect("use-after-invalidate: element no longer valid")
     }
 }
+
+impl<'a, T, G: Generation> DerefMut for ElemMut<'a, T, G> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.parent
+            .get_mut(self.handle)
+            .expect("use-after-invalidate: element no longer valid")
+    }
+}
+
+/// A weak reference that borrows nothing until it is upgraded, like `std::rc::Weak`.
+/// The arena stays free to mutate between upgrades; `upgrade` re-validates each time.
+pub struct WeakHandle<T, G = NonZeroU32> {
+    pub handle: Handle<T, G>,
+}
+
+impl<T, G: Generation> WeakHandle<T, G> {
+    pub fn new(handle: Handle<T, G>) -> Self {
+        Self { handle }
+    }
+
+    /// Resolves against `parent`; `None` after the target was removed or replaced.
+    pub fn upgrade<'a>(&self, parent: &'a DynVec<T, G>) -> Option<Elem<'a, T, G>> {
+        Elem::new(parent, self.handle)
+    }
+
+    /// Mutable upgrade, resolving to an `ElemMut`.
+    pub fn upgrade_mut<'a>(&self, parent: &'a mut DynVec<T, G>) -> Option<ElemMut<'a, T, G>> {
+        ElemMut::new(parent, self.handle)
+    }
+}
+
+// Manual impls so that `WeakHandle<T>` is `Copy` regardless of `T`.
+impl<T, G: Generation> Clone for WeakHandle<T, G> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T, G: Generation> Copy for WeakHandle<T, G> {}
+
+impl<T, G: Generation> fmt::Debug for WeakHandle<T, G> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("WeakHandle").field(&self.handle).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn weak_handle_goes_stale() {
+        let mut vec = DynVec::new();
+        let h = vec.insert(String::from("a"));
+        let weak = WeakHandle::new(h);
+        weak.upgrade_mut(&mut vec).unwrap().push('b');
+        assert_eq!(*weak.upgrade(&vec).unwrap(), "ab");
+        vec.remove(h);
+        vec.insert(String::from("c"));
+        assert!(weak.upgrade(&vec).is_none());
+        assert!(weak.upgrade_mut(&mut vec).is_none());
+    }
+
+    #[test]
+    fn elem_identity() {
+        let mut a = DynVec::new();
+        let mut b = DynVec::new();
+        let ha = a.insert(1);
+        let hb = b.insert(1);
+        // Same index, generation and value, but different arenas
+        assert_eq!((ha.idx, ha.generation), (hb.idx, hb.generation));
+        let ea = Elem::new(&a, ha).unwrap();
+        let copy = ea;
+        assert_eq!(ea, copy);
+        assert_ne!(ea, Elem::new(&b, hb).unwrap());
+        assert!(Elem::new(&b, ha).is_none());
+    }
+}